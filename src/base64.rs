@@ -0,0 +1,47 @@
+// A minimal implementation of standard (RFC 4648) base64 with padding, as used by the terminal
+// graphics protocols
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+        for (index, shift) in [18, 12, 6, 0].iter().enumerate() {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[((triple >> shift) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub(crate) fn decode(encoded: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut accumulator: u32 = 0;
+    let mut accumulated_bits = 0;
+    for byte in encoded {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' | b'\n' | b'\r' => continue,
+            _ => return Err("Invalid base64 data"),
+        };
+        accumulator = (accumulator << 6) | value as u32;
+        accumulated_bits += 6;
+        if accumulated_bits >= 8 {
+            accumulated_bits -= 8;
+            decoded.push((accumulator >> accumulated_bits) as u8);
+            accumulator &= (1 << accumulated_bits) - 1;
+        }
+    }
+    Ok(decoded)
+}
@@ -0,0 +1,273 @@
+//! A command-line tool for inspecting and converting sixel images, see `sixel help`
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+use sixel_image::{SixelColor, SixelImage, TextRenderMode};
+
+const USAGE: &str = "\
+Usage: sixel <command> [arguments]
+
+Commands:
+    info [FILE...]                          describe each image: size, DCS parameters, colors
+    crop <X> <Y> <WIDTH> <HEIGHT> [FILE]    keep only a rectangle of the image
+    scale <WIDTH> <HEIGHT> [FILE]           scale the image with nearest-neighbor sampling
+    to-png [FILE]                           convert the image to PNG
+    from-png [FILE]                         convert a PNG image to sixel
+    normalize [FILE]                        re-serialize the image in canonical form
+    cat [--text COLUMNS] [FILE...]          print the images to the terminal, or render them
+                                            with half blocks for terminals without sixel
+    help                                    print this message
+
+Images are read from FILE, or from standard input if it is missing or `-`, and written to
+standard output. Coordinates and sizes are in pixels.";
+
+fn main() -> ExitCode {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    match run(&arguments) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("sixel: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(arguments: &[String]) -> Result<(), String> {
+    let (command, arguments) = match arguments.split_first() {
+        Some((command, arguments)) => (command.as_str(), arguments),
+        None => return Err(format!("missing command\n\n{}", USAGE)),
+    };
+    match command {
+        "info" => info(arguments),
+        "crop" => crop(arguments),
+        "scale" => scale(arguments),
+        "to-png" => to_png(arguments),
+        "from-png" => from_png(arguments),
+        "normalize" => normalize(arguments),
+        "cat" => cat(arguments),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command: {}\n\n{}", command, USAGE)),
+    }
+}
+
+fn info(arguments: &[String]) -> Result<(), String> {
+    let mut output = String::new();
+    for path in paths(arguments) {
+        let sixel_images = SixelImage::parse_all(&read_input(path)?).map_err(|e| e.to_string())?;
+        for (index, sixel_image) in sixel_images.iter().enumerate() {
+            output.push_str(&format!("{} (image {}):\n", path, index + 1));
+            output.push_str(&describe(sixel_image));
+        }
+    }
+    write_output(output.as_bytes())
+}
+
+fn crop(arguments: &[String]) -> Result<(), String> {
+    let (numbers, path) = split_numbers(arguments, 4, "crop <X> <Y> <WIDTH> <HEIGHT> [FILE]")?;
+    let mut sixel_image = read_image(path)?;
+    sixel_image.crop(numbers[0], numbers[1], numbers[2], numbers[3]);
+    write_output(sixel_image.serialize().as_bytes())
+}
+
+fn scale(arguments: &[String]) -> Result<(), String> {
+    let (numbers, path) = split_numbers(arguments, 2, "scale <WIDTH> <HEIGHT> [FILE]")?;
+    let mut sixel_image = read_image(path)?;
+    sixel_image.scale(numbers[0], numbers[1]);
+    write_output(sixel_image.serialize().as_bytes())
+}
+
+#[cfg(feature = "png")]
+fn to_png(arguments: &[String]) -> Result<(), String> {
+    let sixel_image = read_image(single_path(arguments)?)?;
+    let mut png = vec![];
+    sixel_image.write_png(&mut png)?;
+    write_output(&png)
+}
+
+#[cfg(not(feature = "png"))]
+fn to_png(_arguments: &[String]) -> Result<(), String> {
+    Err("to-png requires building with the `png` feature".to_string())
+}
+
+#[cfg(feature = "png")]
+fn from_png(arguments: &[String]) -> Result<(), String> {
+    let png = read_input(single_path(arguments)?)?;
+    let sixel_image = SixelImage::from_png(png.as_slice(), &Default::default())?;
+    write_output(sixel_image.serialize().as_bytes())
+}
+
+#[cfg(not(feature = "png"))]
+fn from_png(_arguments: &[String]) -> Result<(), String> {
+    Err("from-png requires building with the `png` feature".to_string())
+}
+
+fn normalize(arguments: &[String]) -> Result<(), String> {
+    let sixel_image = read_image(single_path(arguments)?)?;
+    write_output(sixel_image.serialize_canonical().as_bytes())
+}
+
+fn cat(arguments: &[String]) -> Result<(), String> {
+    let (text_columns, arguments) = match arguments.split_first() {
+        Some((flag, arguments)) if flag == "--text" => {
+            let (columns, arguments) = arguments
+                .split_first()
+                .ok_or("usage: sixel cat [--text COLUMNS] [FILE...]")?;
+            (Some(parse_number(columns)?), arguments)
+        }
+        _ => (None, arguments),
+    };
+    let mut output = String::new();
+    for path in paths(arguments) {
+        let sixel_images = SixelImage::parse_all(&read_input(path)?).map_err(|e| e.to_string())?;
+        for sixel_image in sixel_images {
+            match text_columns {
+                Some(columns) => {
+                    // every cell is two pixels high, so halve the rows to keep the aspect ratio
+                    let (height, width) = sixel_image.pixel_size();
+                    let rows = (height * columns).div_ceil(std::cmp::max(width, 1) * 2);
+                    let lines =
+                        sixel_image.render_text(columns, rows, TextRenderMode::HalfBlocksTrueColor);
+                    for line in lines {
+                        output.push_str(&line);
+                        output.push('\n');
+                    }
+                }
+                None => {
+                    output.push_str(&sixel_image.serialize());
+                    output.push('\n');
+                }
+            }
+        }
+    }
+    write_output(output.as_bytes())
+}
+
+fn describe(sixel_image: &SixelImage) -> String {
+    let (height, width) = sixel_image.pixel_size();
+    let mut description = format!("  size: {}x{} pixels\n", width, height);
+    match sixel_image.ra() {
+        Some(ra) => {
+            let declared = |size: Option<usize>| match size {
+                Some(size) => size.to_string(),
+                None => "?".to_string(),
+            };
+            description.push_str(&format!(
+                "  declared size: {}x{} pixels, aspect ratio {}:{}\n",
+                declared(ra.ph()),
+                declared(ra.pv()),
+                ra.pan(),
+                ra.pad()
+            ));
+        }
+        None => description.push_str("  declared size: none\n"),
+    }
+    let dcs = sixel_image.dcs();
+    description.push_str(&format!(
+        "  DCS: macro parameter {}, {} background\n",
+        dcs.macro_parameter(),
+        if dcs.transparent_bg() {
+            "transparent"
+        } else {
+            "opaque"
+        }
+    ));
+    let stats = sixel_image.stats();
+    description.push_str(&format!(
+        "  color registers: {} defined, {} used\n",
+        stats.defined_registers, stats.used_registers
+    ));
+    for (color_register, count) in &stats.register_pixel_counts {
+        let defined = sixel_image.color_registers.contains_key(color_register);
+        description.push_str(&format!(
+            "    #{:<4} {:<24} {} pixels{}\n",
+            color_register,
+            describe_color(sixel_image.register_color(*color_register)),
+            count,
+            if defined { "" } else { " (undefined register)" }
+        ));
+    }
+    match stats.painted_bounds {
+        Some(bounds) => description.push_str(&format!(
+            "  painted area: {}x{} pixels at {},{}\n",
+            bounds.width, bounds.height, bounds.x, bounds.y
+        )),
+        None => description.push_str("  painted area: none\n"),
+    }
+    description.push_str(&format!("  off pixels: {}\n", stats.off_pixels));
+    description
+}
+
+fn describe_color(color: SixelColor) -> String {
+    let (r, g, b) = color.to_rgb8();
+    match color {
+        SixelColor::Rgb(..) => format!("rgb #{:02x}{:02x}{:02x}", r, g, b),
+        SixelColor::Hsl(h, l, s) => format!("hls {},{},{} #{:02x}{:02x}{:02x}", h, l, s, r, g, b),
+    }
+}
+
+// the input paths, standard input if none were given
+fn paths(arguments: &[String]) -> Vec<&str> {
+    if arguments.is_empty() {
+        vec!["-"]
+    } else {
+        arguments.iter().map(|path| path.as_str()).collect()
+    }
+}
+
+fn single_path(arguments: &[String]) -> Result<&str, String> {
+    match arguments {
+        [] => Ok("-"),
+        [path] => Ok(path),
+        _ => Err("expected at most one file".to_string()),
+    }
+}
+
+// the first `count` arguments as numbers, followed by an optional path
+fn split_numbers<'a>(
+    arguments: &'a [String],
+    count: usize,
+    usage: &str,
+) -> Result<(Vec<usize>, &'a str), String> {
+    if arguments.len() < count {
+        return Err(format!("usage: sixel {}", usage));
+    }
+    let numbers = arguments[..count]
+        .iter()
+        .map(|argument| parse_number(argument))
+        .collect::<Result<Vec<usize>, String>>()?;
+    Ok((numbers, single_path(&arguments[count..])?))
+}
+
+fn parse_number(argument: &str) -> Result<usize, String> {
+    argument
+        .parse()
+        .map_err(|_| format!("expected a number, got: {}", argument))
+}
+
+fn read_image(path: &str) -> Result<SixelImage, String> {
+    SixelImage::new(&read_input(path)?).map_err(|e| e.to_string())
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    let result = if path == "-" {
+        io::stdin().read_to_end(&mut bytes)
+    } else {
+        File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+    };
+    result.map_err(|e| format!("{}: {}", path, e))?;
+    Ok(bytes)
+}
+
+fn write_output(bytes: &[u8]) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(bytes)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| e.to_string())
+}
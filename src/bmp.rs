@@ -0,0 +1,141 @@
+// Reading and writing uncompressed Windows bitmaps, see:
+// https://learn.microsoft.com/en-us/windows/win32/gdi/bitmap-storage
+
+use crate::{RgbaImportOptions, SixelImage};
+
+const FILE_HEADER_SIZE: usize = 14;
+const V4_HEADER_SIZE: usize = 108;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+const LCS_SRGB: u32 = 0x7352_4742;
+
+/// Encodes the image as a 32-bit BGRA bitmap with a `BITMAPV4HEADER`, so that the alpha channel
+/// is honored, pixels that are off are fully transparent
+pub(crate) fn to_bmp(sixel_image: &SixelImage) -> Vec<u8> {
+    let (height, width) = sixel_image.pixel_size();
+    let data_offset = FILE_HEADER_SIZE + V4_HEADER_SIZE;
+    let file_size = data_offset + width * height * 4;
+    let mut bmp = Vec::with_capacity(file_size);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(file_size as u32).to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]); // reserved
+    bmp.extend_from_slice(&(data_offset as u32).to_le_bytes());
+    bmp.extend_from_slice(&(V4_HEADER_SIZE as u32).to_le_bytes());
+    bmp.extend_from_slice(&(width as i32).to_le_bytes());
+    bmp.extend_from_slice(&(height as i32).to_le_bytes()); // positive, so rows are bottom-up
+    bmp.extend_from_slice(&1u16.to_le_bytes()); // planes
+    bmp.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+    bmp.extend_from_slice(&BI_BITFIELDS.to_le_bytes());
+    bmp.extend_from_slice(&((width * height * 4) as u32).to_le_bytes());
+    bmp.extend_from_slice(&2835u32.to_le_bytes()); // 72 DPI horizontally
+    bmp.extend_from_slice(&2835u32.to_le_bytes()); // and vertically
+    bmp.extend_from_slice(&[0; 8]); // no palette
+    for mask in [0x00ff_0000u32, 0x0000_ff00, 0x0000_00ff, 0xff00_0000] {
+        bmp.extend_from_slice(&mask.to_le_bytes());
+    }
+    bmp.extend_from_slice(&LCS_SRGB.to_le_bytes());
+    bmp.extend_from_slice(&[0; 48]); // endpoints and gamma, unused for sRGB
+    let rgba = sixel_image.to_rgba();
+    for y in (0..height).rev() {
+        for pixel in rgba[y * width * 4..(y + 1) * width * 4].chunks_exact(4) {
+            bmp.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+    bmp
+}
+
+/// Decodes an uncompressed 24-bit or 32-bit bitmap, quantizing its colors (see
+/// [`SixelImage::from_rgba`])
+pub(crate) fn from_bmp(
+    bytes: &[u8],
+    options: &RgbaImportOptions,
+) -> Result<SixelImage, &'static str> {
+    if bytes.get(..2) != Some(&b"BM"[..]) {
+        return Err("Not a BMP image");
+    }
+    let data_offset = read_u32(bytes, 10)? as usize;
+    let header_size = read_u32(bytes, 14)? as usize;
+    if header_size < 40 {
+        return Err("Unsupported BMP header");
+    }
+    let width = read_u32(bytes, 18)? as i32;
+    let height = read_u32(bytes, 22)? as i32;
+    let bits_per_pixel = read_u16(bytes, 28)?;
+    let compression = read_u32(bytes, 30)?;
+    // rejecting empty images also keeps a huge height from passing the data length check
+    if width <= 0 || height == 0 {
+        return Err("Invalid BMP size");
+    }
+    let masks = match (compression, bits_per_pixel) {
+        (BI_RGB, 24) | (BI_RGB, 32) => [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0],
+        (BI_BITFIELDS, 32) | (BI_ALPHABITFIELDS, 32) => {
+            // the masks follow a BITMAPINFOHEADER and are a part of the larger headers
+            let has_alpha_mask = compression == BI_ALPHABITFIELDS || header_size >= 56;
+            [
+                read_u32(bytes, 54)?,
+                read_u32(bytes, 58)?,
+                read_u32(bytes, 62)?,
+                if has_alpha_mask {
+                    read_u32(bytes, 66)?
+                } else {
+                    0
+                },
+            ]
+        }
+        _ => return Err("Unsupported BMP pixel format"),
+    };
+    let is_top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let row_size = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let data = bytes
+        .get(data_offset..)
+        .and_then(|data| data.get(..row_size.checked_mul(height)?))
+        .ok_or("Image data is shorter than its size")?;
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row_index = if is_top_down { y } else { height - 1 - y };
+        let row = &data[row_index * row_size..][..width * bytes_per_pixel];
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            let mut value = [0; 4];
+            value[..bytes_per_pixel].copy_from_slice(pixel);
+            let value = u32::from_le_bytes(value);
+            rgba.extend_from_slice(&[
+                channel(value, masks[0]),
+                channel(value, masks[1]),
+                channel(value, masks[2]),
+                if masks[3] == 0 {
+                    255
+                } else {
+                    channel(value, masks[3])
+                },
+            ]);
+        }
+    }
+    SixelImage::from_rgba(width, height, &rgba, options)
+}
+
+// extracts the bits of a channel mask, scaled to 8 bits
+fn channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = (value & mask) >> mask.trailing_zeros();
+    let max_value = mask >> mask.trailing_zeros();
+    ((bits as u64 * 255 + max_value as u64 / 2) / max_value as u64) as u8
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, &'static str> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|value| u16::from_le_bytes([value[0], value[1]]))
+        .ok_or("BMP header is truncated")
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, &'static str> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        .ok_or("BMP header is truncated")
+}
@@ -0,0 +1,153 @@
+// Comparing images by the colors their pixels resolve to rather than by their color registers,
+// so that the same picture serialized with different register numbers compares equal
+
+use crate::{RgbaImportOptions, SixelImage};
+
+/// How much two images of the same size differ, see [`SixelImage::difference`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageDifference {
+    /// The number of pixels whose colors differ, or that are only on in one of the images
+    pub differing_pixels: usize,
+    /// The average perceptual difference over all pixels, from 0.0 (identical) to 1.0
+    pub mean_difference: f64,
+    /// The largest perceptual difference of a single pixel, from 0.0 (identical) to 1.0
+    pub max_difference: f64,
+}
+
+// the color of the differing pixels in visual diffs, and the lightness identical pixels are
+// faded towards so that the differences stand out
+const DIFF_HIGHLIGHT: (u8, u8, u8) = (255, 0, 0);
+const FADE_LIGHTNESS: u32 = 192;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub(crate) fn images_equal(sixel_image: &SixelImage, other: &SixelImage) -> bool {
+    if sixel_image.pixel_size() != other.pixel_size() {
+        return false;
+    }
+    let (height, width) = compared_size(sixel_image, other);
+    (0..height).all(|y| (0..width).all(|x| sixel_image.rgb_at(x, y) == other.rgb_at(x, y)))
+}
+
+// FNV-1a over the size of the image and the resolved color of each of its pixels, so that images
+// that are equal (see `images_equal`) have the same hash. Pixels of rows longer than the image
+// width only count if they are on, since a missing pixel compares equal to one that is off
+pub(crate) fn content_hash(sixel_image: &SixelImage) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut hash_bytes = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    let (height, width) = sixel_image.pixel_size();
+    hash_bytes(&(width as u64).to_le_bytes());
+    hash_bytes(&(height as u64).to_le_bytes());
+    for (y, row) in sixel_image.pixels.iter().enumerate() {
+        for x in 0..width {
+            match sixel_image.rgb_at(x, y) {
+                Some((r, g, b)) => hash_bytes(&[1, r, g, b]),
+                None => hash_bytes(&[0]),
+            }
+        }
+        for x in width..row.len() {
+            if let Some((r, g, b)) = sixel_image.rgb_at(x, y) {
+                hash_bytes(&[2]);
+                hash_bytes(&(x as u64).to_le_bytes());
+                hash_bytes(&[r, g, b]);
+            }
+        }
+    }
+    hash
+}
+
+pub(crate) fn difference(
+    sixel_image: &SixelImage,
+    other: &SixelImage,
+) -> Result<ImageDifference, &'static str> {
+    if sixel_image.pixel_size() != other.pixel_size() {
+        return Err("Cannot compare images of different sizes");
+    }
+    let (height, width) = compared_size(sixel_image, other);
+    let mut differing_pixels = 0;
+    let mut total_difference = 0.0;
+    let mut max_difference: f64 = 0.0;
+    for y in 0..height {
+        for x in 0..width {
+            let pixel_difference = pixel_difference(sixel_image.rgb_at(x, y), other.rgb_at(x, y));
+            if pixel_difference > 0.0 {
+                differing_pixels += 1;
+            }
+            total_difference += pixel_difference;
+            max_difference = max_difference.max(pixel_difference);
+        }
+    }
+    let pixel_count = std::cmp::max(width * height, 1);
+    Ok(ImageDifference {
+        differing_pixels,
+        mean_difference: total_difference / pixel_count as f64,
+        max_difference,
+    })
+}
+
+// An image the size of both images, showing the pixels that differ in red on top of a faded
+// copy of `sixel_image`
+pub(crate) fn diff_image(
+    sixel_image: &SixelImage,
+    other: &SixelImage,
+) -> Result<SixelImage, &'static str> {
+    if sixel_image.pixel_size() != other.pixel_size() {
+        return Err("Cannot compare images of different sizes");
+    }
+    let (height, width) = compared_size(sixel_image, other);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let (rgb, other_rgb) = (sixel_image.rgb_at(x, y), other.rgb_at(x, y));
+            let (r, g, b) = match (rgb, other_rgb) {
+                (None, None) => {
+                    rgba.extend_from_slice(&[0, 0, 0, 0]);
+                    continue;
+                }
+                _ if rgb != other_rgb => DIFF_HIGHLIGHT,
+                _ => fade(rgb.unwrap_or((0, 0, 0))),
+            };
+            rgba.extend_from_slice(&[r, g, b, 255]);
+        }
+    }
+    SixelImage::from_rgba(width, height, &rgba, &RgbaImportOptions::default())
+}
+
+// The height and the width of the longest row of both images, pixels missing from shorter rows
+// are compared as if they were off
+fn compared_size(sixel_image: &SixelImage, other: &SixelImage) -> (usize, usize) {
+    let width = std::cmp::max(sixel_image.max_row_width(), other.max_row_width());
+    (sixel_image.pixels.len(), width)
+}
+
+// The "redmean" approximation of perceived color distance, scaled so that black and white are
+// 1.0 apart. A pixel that is only on in one of the images is as different as can be
+fn pixel_difference(rgb: Option<(u8, u8, u8)>, other_rgb: Option<(u8, u8, u8)>) -> f64 {
+    let ((r1, g1, b1), (r2, g2, b2)) = match (rgb, other_rgb) {
+        (None, None) => return 0.0,
+        (Some(rgb), Some(other_rgb)) => (rgb, other_rgb),
+        _ => return 1.0,
+    };
+    let red_mean = (r1 as f64 + r2 as f64) / 2.0;
+    let delta_r = r1 as f64 - r2 as f64;
+    let delta_g = g1 as f64 - g2 as f64;
+    let delta_b = b1 as f64 - b2 as f64;
+    let distance = ((2.0 + red_mean / 256.0) * delta_r * delta_r
+        + 4.0 * delta_g * delta_g
+        + (2.0 + (255.0 - red_mean) / 256.0) * delta_b * delta_b)
+        .sqrt();
+    let max_distance = f64::sqrt((4.0 + 255.0 / 256.0) * 255.0 * 255.0 + 4.0 * 255.0 * 255.0);
+    (distance / max_distance).min(1.0)
+}
+
+// a light gray with the brightness of `rgb`, so that identical pixels fade into the background
+fn fade(rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+    let gray = (FADE_LIGHTNESS + crate::luminance(rgb) as u32 * (255 - FADE_LIGHTNESS) / 255) as u8;
+    (gray, gray, gray)
+}
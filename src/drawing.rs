@@ -0,0 +1,132 @@
+// In-place drawing on the pixels of an image. Everything is clipped to the image: pixels outside
+// of it (or missing from rows shorter than the image width) are left untouched
+
+use crate::{Pixel, Rect, SixelImage};
+
+pub(crate) fn fill_rect(sixel_image: &mut SixelImage, rect: Rect, color_register: u16) {
+    let (height, width) = sixel_image.pixel_size();
+    let right = std::cmp::min(rect.x.saturating_add(rect.width), width);
+    let bottom = std::cmp::min(rect.y.saturating_add(rect.height), height);
+    for y in rect.y..bottom {
+        for x in rect.x..right {
+            paint(sixel_image, x, y, color_register);
+        }
+    }
+}
+
+pub(crate) fn draw_rect_outline(sixel_image: &mut SixelImage, rect: Rect, color_register: u16) {
+    let (height, width) = sixel_image.pixel_size();
+    if rect.width == 0 || rect.height == 0 || rect.x >= width || rect.y >= height {
+        return;
+    }
+    let right = rect.x.saturating_add(rect.width - 1);
+    let bottom = rect.y.saturating_add(rect.height - 1);
+    for x in rect.x..=std::cmp::min(right, width - 1) {
+        paint(sixel_image, x, rect.y, color_register);
+        paint(sixel_image, x, bottom, color_register);
+    }
+    for y in rect.y..=std::cmp::min(bottom, height - 1) {
+        paint(sixel_image, rect.x, y, color_register);
+        paint(sixel_image, right, y, color_register);
+    }
+}
+
+// The pixels closest to the line between both end points (included), as Bresenham's algorithm
+// would draw them. Only the steps along the longer axis that are inside the image are walked, so
+// lines that start or end far outside of it are as cheap as the part of them that is visible
+pub(crate) fn draw_line(
+    sixel_image: &mut SixelImage,
+    from: (usize, usize),
+    to: (usize, usize),
+    color_register: u16,
+) {
+    let (height, width) = sixel_image.pixel_size();
+    // walk the longer (major) axis one pixel at a time, as if it were the x axis
+    let steep = from.1.abs_diff(to.1) > from.0.abs_diff(to.0);
+    let transpose = |(x, y): (usize, usize)| if steep { (y, x) } else { (x, y) };
+    let (from, to) = (transpose(from), transpose(to));
+    let major_size = if steep { height } else { width };
+    if major_size == 0 {
+        return;
+    }
+    let major_length = from.0.abs_diff(to.0);
+    let minor_length = from.1.abs_diff(to.1);
+    let steps = if from.0 <= to.0 {
+        0..=std::cmp::min(major_length, (major_size - 1).saturating_sub(from.0))
+    } else {
+        from.0.saturating_sub(major_size - 1)..=major_length
+    };
+    for step in steps {
+        let major = if from.0 <= to.0 {
+            from.0 + step
+        } else {
+            from.0 - step
+        };
+        let minor_offset = scale_rounded(step, minor_length, major_length);
+        let minor = if from.1 <= to.1 {
+            from.1 + minor_offset
+        } else {
+            from.1 - minor_offset
+        };
+        let (x, y) = transpose((major, minor));
+        paint(sixel_image, x, y, color_register);
+    }
+}
+
+// Paints the 4-connected area of pixels like the one at (x, y): either all off, or all on with
+// the same color register
+pub(crate) fn flood_fill(sixel_image: &mut SixelImage, x: usize, y: usize, color_register: u16) {
+    let (height, width) = sixel_image.pixel_size();
+    let target = match sixel_image.pixel_at(x, y) {
+        Some(pixel) if x < width && y < height => *pixel,
+        _ => return,
+    };
+    if target.on && target.color == color_register {
+        return; // already filled, and would otherwise never stop
+    }
+    let is_target =
+        |pixel: &Pixel| pixel.on == target.on && (!pixel.on || pixel.color == target.color);
+    let mut to_visit = vec![(x, y)];
+    while let Some((x, y)) = to_visit.pop() {
+        match sixel_image.pixel_at(x, y) {
+            Some(pixel) if is_target(pixel) => {}
+            _ => continue,
+        }
+        paint(sixel_image, x, y, color_register);
+        if x > 0 {
+            to_visit.push((x - 1, y));
+        }
+        if x + 1 < width {
+            to_visit.push((x + 1, y));
+        }
+        if y > 0 {
+            to_visit.push((x, y - 1));
+        }
+        if y + 1 < height {
+            to_visit.push((x, y + 1));
+        }
+    }
+}
+
+fn paint(sixel_image: &mut SixelImage, x: usize, y: usize, color_register: u16) {
+    let (_, width) = sixel_image.pixel_size();
+    if x >= width {
+        return;
+    }
+    if let Some(pixel) = sixel_image.pixels.get_mut(y).and_then(|row| row.get_mut(x)) {
+        *pixel = Pixel {
+            on: true,
+            color: color_register,
+        };
+    }
+}
+
+// `value * numerator / denominator` rounded to the nearest integer, without overflowing
+fn scale_rounded(value: usize, numerator: usize, denominator: usize) -> usize {
+    if denominator == 0 {
+        return 0;
+    }
+    let product = value as u128 * numerator as u128;
+    let (quotient, remainder) = (product / denominator as u128, product % denominator as u128);
+    (quotient + u128::from(2 * remainder >= denominator as u128)) as usize
+}
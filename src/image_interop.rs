@@ -0,0 +1,147 @@
+// Interoperability with the `image` crate: conversions to and from its image buffers, as well as
+// an encoder and a decoder so that sixel can be used wherever the `image` crate expects them
+
+use std::io::{Read, Write};
+
+use image::error::{
+    DecodingError, EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind,
+};
+use image::{
+    ColorType, DynamicImage, ExtendedColorType, ImageDecoder, ImageEncoder, ImageError,
+    ImageResult, RgbaImage,
+};
+
+use crate::{RgbaImportOptions, SixelImage};
+
+impl From<&SixelImage> for RgbaImage {
+    /// Pixels that are off are fully transparent
+    fn from(sixel_image: &SixelImage) -> Self {
+        let (height, width) = sixel_image.pixel_size();
+        RgbaImage::from_raw(width as u32, height as u32, sixel_image.to_rgba())
+            .unwrap_or_else(|| RgbaImage::new(0, 0))
+    }
+}
+
+impl From<&SixelImage> for DynamicImage {
+    /// Pixels that are off are fully transparent
+    fn from(sixel_image: &SixelImage) -> Self {
+        DynamicImage::ImageRgba8(sixel_image.into())
+    }
+}
+
+impl TryFrom<&RgbaImage> for SixelImage {
+    type Error = &'static str;
+    /// Quantizes the colors of the image (see [`SixelImage::from_rgba`])
+    fn try_from(rgba_image: &RgbaImage) -> Result<Self, Self::Error> {
+        SixelImage::from_rgba(
+            rgba_image.width() as usize,
+            rgba_image.height() as usize,
+            rgba_image.as_raw(),
+            &RgbaImportOptions::default(),
+        )
+    }
+}
+
+impl TryFrom<&DynamicImage> for SixelImage {
+    type Error = &'static str;
+    /// Converts the image to 8-bit RGBA and quantizes its colors (see [`SixelImage::from_rgba`])
+    fn try_from(dynamic_image: &DynamicImage) -> Result<Self, Self::Error> {
+        SixelImage::try_from(&dynamic_image.to_rgba8())
+    }
+}
+
+/// An [`ImageEncoder`] writing 8-bit grayscale or RGB images, with or without alpha, as a sixel
+/// image (`ESC P ... ESC \`)
+pub struct SixelEncoder<W: Write> {
+    writer: W,
+    options: RgbaImportOptions,
+}
+
+impl<W: Write> SixelEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        SixelEncoder {
+            writer,
+            options: RgbaImportOptions::default(),
+        }
+    }
+    /// Controls how partially transparent pixels are encoded, see
+    /// [`SixelImage::from_rgba`]
+    pub fn import_options(mut self, options: RgbaImportOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+impl<W: Write> ImageEncoder for SixelEncoder<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()> {
+        let rgba: Vec<u8> = match color_type {
+            ExtendedColorType::Rgba8 => buf.to_vec(),
+            ExtendedColorType::Rgb8 => buf
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            ExtendedColorType::La8 => buf
+                .chunks_exact(2)
+                .flat_map(|la| [la[0], la[0], la[0], la[1]])
+                .collect(),
+            ExtendedColorType::L8 => buf.iter().flat_map(|l| [*l, *l, *l, 255]).collect(),
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        format_hint(),
+                        UnsupportedErrorKind::Color(color_type),
+                    ),
+                ))
+            }
+        };
+        let sixel_image =
+            SixelImage::from_rgba(width as usize, height as usize, &rgba, &self.options)
+                .map_err(|e| ImageError::Encoding(EncodingError::new(format_hint(), e)))?;
+        self.writer.write_all(sixel_image.serialize().as_bytes())?;
+        Ok(())
+    }
+}
+
+/// An [`ImageDecoder`] reading the first sixel image in its input as 8-bit RGBA, pixels that are
+/// off are fully transparent
+pub struct SixelDecoder {
+    sixel_image: SixelImage,
+}
+
+impl SixelDecoder {
+    /// Reads all of `reader` and parses the first sixel image in it
+    pub fn new<R: Read>(mut reader: R) -> ImageResult<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let sixel_image = SixelImage::new(&bytes)
+            .map_err(|e| ImageError::Decoding(DecodingError::new(format_hint(), e)))?;
+        Ok(SixelDecoder { sixel_image })
+    }
+}
+
+impl ImageDecoder for SixelDecoder {
+    fn dimensions(&self) -> (u32, u32) {
+        let (height, width) = self.sixel_image.pixel_size();
+        (width as u32, height as u32)
+    }
+    fn color_type(&self) -> ColorType {
+        ColorType::Rgba8
+    }
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        buf.copy_from_slice(&self.sixel_image.to_rgba());
+        Ok(())
+    }
+    fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        (*self).read_image(buf)
+    }
+}
+
+fn format_hint() -> ImageFormatHint {
+    ImageFormatHint::Name("sixel".into())
+}
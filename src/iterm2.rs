@@ -0,0 +1,31 @@
+// Conversion of sixel images to the iTerm2 inline images protocol, see:
+// https://iterm2.com/documentation-images.html
+
+use crate::{base64, png_writer, SixelImage};
+
+/// Serializes the image as an iTerm2 `OSC 1337 ; File=...` inline image sequence with an
+/// embedded PNG, optionally scaled to the given size in cells
+pub(crate) fn serialize(
+    sixel_image: &SixelImage,
+    width_in_cells: Option<usize>,
+    height_in_cells: Option<usize>,
+) -> String {
+    let (height, width) = sixel_image.pixel_size();
+    let png = png_writer::encode_rgba(width, height, &sixel_image.to_rgba());
+    let mut arguments = format!("inline=1;size={}", png.len());
+    if let Some(width_in_cells) = width_in_cells {
+        arguments.push_str(&format!(";width={}", width_in_cells));
+    }
+    if let Some(height_in_cells) = height_in_cells {
+        arguments.push_str(&format!(";height={}", height_in_cells));
+    }
+    if width_in_cells.is_some() && height_in_cells.is_some() {
+        // both dimensions were explicitly requested, so the image should fill them exactly
+        arguments.push_str(";preserveAspectRatio=0");
+    }
+    format!(
+        "\u{1b}]1337;File={}:{}\u{7}",
+        arguments,
+        base64::encode(&png)
+    )
+}
@@ -0,0 +1,113 @@
+// Conversion between sixel images and the kitty terminal graphics protocol, see:
+// https://sw.kovidgoyal.net/kitty/graphics-protocol/
+
+use crate::{base64, RgbaImportOptions, SixelImage};
+
+const MAX_CHUNK_SIZE: usize = 4096;
+
+/// Serializes the image as kitty graphics commands that transmit its RGBA pixels and display
+/// them at the cursor position, chunking the base64 payload as the protocol requires
+pub(crate) fn serialize(sixel_image: &SixelImage) -> String {
+    let (height, width) = sixel_image.pixel_size();
+    let payload = base64::encode(&sixel_image.to_rgba());
+    let chunks: Vec<&[u8]> = if payload.is_empty() {
+        vec![&[]]
+    } else {
+        payload.as_bytes().chunks(MAX_CHUNK_SIZE).collect()
+    };
+    let mut serialized = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more_chunks = if index + 1 < chunks.len() { 1 } else { 0 };
+        serialized.push_str("\u{1b}_G");
+        if index == 0 {
+            serialized.push_str(&format!("a=T,f=32,s={},v={},q=2,", width, height));
+        }
+        serialized.push_str(&format!("m={};", more_chunks));
+        serialized.push_str(std::str::from_utf8(chunk).unwrap_or(""));
+        serialized.push_str("\u{1b}\\");
+    }
+    serialized
+}
+
+/// Constructs a [`SixelImage`] out of the first image transmitted by the kitty graphics commands
+/// in `bytes`, joining its chunks. Only uncompressed RGB and RGBA data transmitted directly is
+/// supported
+pub(crate) fn deserialize(
+    bytes: &[u8],
+    options: &RgbaImportOptions,
+) -> Result<SixelImage, &'static str> {
+    let mut format = 32;
+    let mut width = None;
+    let mut height = None;
+    let mut payload = vec![];
+    let mut is_first_chunk = true;
+    for (control_data, chunk) in graphics_commands(bytes) {
+        let mut more_chunks = false;
+        for (key, value) in control_data
+            .split(|byte| *byte == b',')
+            .filter_map(|key_value| key_value.split_first())
+        {
+            let value = value.strip_prefix(b"=").unwrap_or(value);
+            match (key, is_first_chunk) {
+                (b'm', _) => more_chunks = value == b"1",
+                (b'f', true) => format = parse_number(value)?,
+                (b's', true) => width = Some(parse_number(value)?),
+                (b'v', true) => height = Some(parse_number(value)?),
+                (b'o', true) => return Err("Compressed kitty images are not supported"),
+                (b't', true) if value != b"d" => {
+                    return Err("Only directly transmitted kitty images are supported")
+                }
+                _ => {}
+            }
+        }
+        payload.extend_from_slice(chunk);
+        is_first_chunk = false;
+        if !more_chunks {
+            break;
+        }
+    }
+    if is_first_chunk {
+        return Err("No kitty graphics command found");
+    }
+    let data = base64::decode(&payload)?;
+    let width = width.ok_or("Kitty image is missing its width")?;
+    let height = height.ok_or("Kitty image is missing its height")?;
+    match format {
+        32 => SixelImage::from_rgba(width, height, &data, options),
+        24 => {
+            let rgb_len = width
+                .checked_mul(height)
+                .and_then(|pixel_count| pixel_count.checked_mul(3))
+                .ok_or("Image size is too large")?;
+            if data.len() != rgb_len {
+                return Err("RGB data does not match the image size");
+            }
+            let rgba: Vec<u8> = data
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect();
+            SixelImage::from_rgba(width, height, &rgba, options)
+        }
+        _ => Err("Unsupported kitty image format"),
+    }
+}
+
+// the control data and payload of every graphics command (ESC _ G ... ESC \) in `bytes`
+fn graphics_commands(bytes: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    bytes
+        .split(|byte| *byte == 0x1b)
+        .filter_map(|sequence| sequence.strip_prefix(b"_G"))
+        .map(
+            |command| match command.iter().position(|byte| *byte == b';') {
+                Some(separator) => (&command[..separator], &command[separator + 1..]),
+                None => (command, &command[command.len()..]),
+            },
+        )
+}
+
+fn parse_number(value: &[u8]) -> Result<usize, &'static str> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or("Invalid kitty graphics command")
+}
@@ -4,6 +4,7 @@
 //!
 //! 1. If you already have all the serialized sixel bytes, construct [`SixelImage`] directly
 //! 2. If you'd like to parse bytes in real time "on the wire", use [`SixelDeserializer`]
+//! 3. If the sixel bytes are embedded in other terminal output, find them with [`SixelScanner`]
 //!
 //! # Example
 //! ```no_run
@@ -23,40 +24,75 @@
 //! }
 //! ```
 
+mod base64;
+mod bmp;
+mod comparison;
+mod drawing;
+#[cfg(feature = "image")]
+mod image_interop;
+mod iterm2;
+mod kitty;
+mod netpbm;
+#[cfg(feature = "png")]
+mod png_codec;
+mod png_writer;
+mod quantizer;
+#[cfg(feature = "serde")]
+mod serde_pixels;
+mod sixel_animation;
+mod sixel_band_cache;
 mod sixel_deserializer;
+mod sixel_scanner;
 mod sixel_serializer;
+mod text_renderer;
 
+pub use comparison::ImageDifference;
+#[cfg(feature = "image")]
+pub use image_interop::{SixelDecoder, SixelEncoder};
+pub use sixel_animation::{SixelAnimation, SixelFrame};
+pub use sixel_band_cache::SixelBandCache;
 pub use sixel_deserializer::SixelDeserializer;
+pub use sixel_scanner::{EmbeddedSixelImage, SixelScanner};
 pub use sixel_serializer::SixelSerializer;
+pub use text_renderer::TextRenderMode;
 
+use quantizer::Quantizer;
 use sixel_tokenizer::{ColorCoordinateSystem, Parser};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use text_renderer::TextRenderer;
+
+// The number of color registers images created from other formats are quantized to, and the
+// default limit of color registers new colors are allocated in
+const MAX_COLOR_REGISTERS: usize = 256;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SixelImage {
     pub color_registers: BTreeMap<u16, SixelColor>,
+    #[cfg_attr(feature = "serde", serde(with = "serde_pixels"))]
     pub pixels: Vec<Vec<Pixel>>,
     dcs: DCS,
     ra: Option<RA>,
+    #[cfg_attr(feature = "serde", serde(default = "default_register_limit"))]
+    register_limit: usize,
 }
 
-#[derive(Debug, Clone)]
+#[cfg(feature = "serde")]
+fn default_register_limit() -> usize {
+    MAX_COLOR_REGISTERS
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DCS {
     macro_parameter: u8,
     transparent_bg: bool,
 }
 
-impl Default for DCS {
-    fn default() -> Self {
-        DCS {
-            macro_parameter: 0,
-            transparent_bg: false,
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RA {
     pan: usize,
     pad: usize,
@@ -71,13 +107,139 @@ impl SixelImage {
         let mut sixel_deserializer = SixelDeserializer::new();
         for byte in bytes {
             let mut handle_result = Ok(());
-            parser.advance(&byte, |sixel_event| {
+            parser.advance(byte, |sixel_event| {
                 handle_result = sixel_deserializer.handle_event(sixel_event);
             });
             handle_result?
         }
-        let sixel_image = sixel_deserializer.create_image();
-        sixel_image
+        sixel_deserializer.create_image()
+    }
+    /// Constructs a `SixelImage` out of each of the images in an existing slice of serialized
+    /// sixel bytes, for when several images were concatenated one after the other
+    pub fn parse_all(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        let mut parser = Parser::new();
+        let mut sixel_deserializer = SixelDeserializer::new();
+        let mut sixel_images = vec![];
+        for byte in bytes {
+            let mut handle_result = Ok(());
+            parser.advance(byte, |sixel_event| {
+                handle_result = sixel_deserializer.handle_event(sixel_event);
+            });
+            handle_result?;
+            if sixel_deserializer.is_complete() {
+                sixel_images.push(sixel_deserializer.create_image()?);
+            }
+        }
+        match sixel_deserializer.create_image() {
+            Ok(unterminated_sixel_image) => sixel_images.push(unterminated_sixel_image),
+            Err(e) if sixel_images.is_empty() => return Err(e),
+            Err(_) => {} // nothing after the last image
+        }
+        Ok(sixel_images)
+    }
+    /// Constructs a new `SixelImage` out of 8-bit RGBA pixel data (4 bytes per pixel, row by row).
+    /// Colors are quantized into at most 256 color registers, and `options` control how partially
+    /// transparent pixels are handled (by default, pixels with an alpha lower than 128 are turned
+    /// off, giving the image a transparent background)
+    pub fn from_rgba(
+        width: usize,
+        height: usize,
+        rgba: &[u8],
+        options: &RgbaImportOptions,
+    ) -> Result<Self, &'static str> {
+        let rgba_len = width
+            .checked_mul(height)
+            .and_then(|pixel_count| pixel_count.checked_mul(4))
+            .ok_or("Image size is too large")?;
+        if rgba.len() != rgba_len {
+            return Err("RGBA data does not match the image size");
+        }
+        let is_on = |rgba_pixel: &[u8]| rgba_pixel[3] >= options.alpha_threshold;
+        let rgb_of = |rgba_pixel: &[u8]| {
+            let rgb = (rgba_pixel[0], rgba_pixel[1], rgba_pixel[2]);
+            match options.blend_background {
+                Some(background) => blend(rgb, background, rgba_pixel[3]),
+                None => rgb,
+            }
+        };
+        let mut quantizer = Quantizer::new();
+        for rgba_pixel in rgba.chunks_exact(4).filter(|rgba_pixel| is_on(rgba_pixel)) {
+            quantizer.add(rgb_of(rgba_pixel));
+        }
+        let palette = quantizer.palette(MAX_COLOR_REGISTERS);
+        let pixels: Vec<Vec<Pixel>> = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let offset = (y * width + x) * 4;
+                        let rgba_pixel = &rgba[offset..offset + 4];
+                        if is_on(rgba_pixel) {
+                            Pixel {
+                                on: true,
+                                color: palette.index_of(rgb_of(rgba_pixel)),
+                            }
+                        } else {
+                            Pixel {
+                                on: false,
+                                color: 0,
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let transparent_bg = pixels.iter().flatten().any(|pixel| !pixel.on);
+        let color_registers = palette
+            .colors
+            .into_iter()
+            .enumerate()
+            .map(|(index, color)| (index as u16, color))
+            .collect();
+        Ok(SixelImage {
+            color_registers,
+            pixels,
+            dcs: DCS {
+                macro_parameter: 0,
+                transparent_bg,
+            },
+            ra: Some(RA {
+                pan: 1,
+                pad: 1,
+                ph: Some(width),
+                pv: Some(height),
+            }),
+            register_limit: MAX_COLOR_REGISTERS,
+        })
+    }
+    /// Constructs a new `SixelImage` out of the first image transmitted by the kitty graphics
+    /// protocol commands (`ESC _G ... ESC \`) in `bytes`, joining its chunks. Only uncompressed
+    /// 24-bit and 32-bit pixel data transmitted directly is supported
+    pub fn from_kitty(bytes: &[u8], options: &RgbaImportOptions) -> Result<Self, &'static str> {
+        kitty::deserialize(bytes, options)
+    }
+    /// Constructs a new `SixelImage` out of a PNG image of any color type and bit depth, quantizing
+    /// its colors (see [`SixelImage::from_rgba`])
+    #[cfg(feature = "png")]
+    pub fn from_png<R: std::io::Read>(
+        reader: R,
+        options: &RgbaImportOptions,
+    ) -> Result<Self, &'static str> {
+        png_codec::from_png(reader, options)
+    }
+    /// Constructs a new `SixelImage` out of a binary PPM (P6) image, quantizing its colors (see
+    /// [`SixelImage::from_rgba`])
+    pub fn from_ppm(bytes: &[u8]) -> Result<Self, &'static str> {
+        netpbm::from_ppm(bytes)
+    }
+    /// Constructs a new `SixelImage` out of a PAM (P7) image with a grayscale or RGB tuple type,
+    /// with or without alpha, quantizing its colors (see [`SixelImage::from_rgba`])
+    pub fn from_pam(bytes: &[u8], options: &RgbaImportOptions) -> Result<Self, &'static str> {
+        netpbm::from_pam(bytes, options)
+    }
+    /// Constructs a new `SixelImage` out of an uncompressed 24-bit or 32-bit BMP image, quantizing
+    /// its colors (see [`SixelImage::from_rgba`])
+    pub fn from_bmp(bytes: &[u8], options: &RgbaImportOptions) -> Result<Self, &'static str> {
+        bmp::from_bmp(bytes, options)
     }
     /// Returns the (height, width) of the image in pixels
     pub fn pixel_size(&self) -> (usize, usize) {
@@ -90,12 +252,84 @@ impl SixelImage {
         let height = self.pixels.len();
         (height, width)
     }
+    /// Returns the parameters of the device control string that introduced this image
+    pub fn dcs(&self) -> &DCS {
+        &self.dcs
+    }
+    /// Returns the raster attributes of this image, if it declared any
+    pub fn ra(&self) -> Option<&RA> {
+        self.ra.as_ref()
+    }
     /// Serializes the whole image, returning a stringified sixel representation of it
     pub fn serialize(&self) -> String {
         let sixel_serializer =
             SixelSerializer::new(&self.dcs, &self.ra, &self.color_registers, &self.pixels);
-        let serialized_image = sixel_serializer.serialize();
-        serialized_image
+        sixel_serializer.serialize()
+    }
+    /// Serializes the whole image without color registers or color introducers, so that all
+    /// pixels that are on are drawn with the foreground color of monochrome terminals and printers
+    pub fn serialize_monochrome(&self) -> String {
+        let sixel_serializer =
+            SixelSerializer::new(&self.dcs, &self.ra, &self.color_registers, &self.pixels)
+                .monochrome(true);
+        sixel_serializer.serialize()
+    }
+    /// Serializes the whole image in the canonical form of [`SixelSerializer::canonical`], so
+    /// that images with the same pixels serialize to the same bytes however they were encoded
+    pub fn serialize_canonical(&self) -> String {
+        let sixel_serializer =
+            SixelSerializer::new(&self.dcs, &self.ra, &self.color_registers, &self.pixels)
+                .canonical(true);
+        sixel_serializer.serialize()
+    }
+    /// Serializes the whole image as kitty graphics protocol commands that transmit its RGBA
+    /// pixels and display them at the cursor position
+    pub fn serialize_kitty(&self) -> String {
+        kitty::serialize(self)
+    }
+    /// Serializes the whole image as an iTerm2 inline image (`OSC 1337 ; File=...`) with an
+    /// embedded PNG, as supported by eg. iTerm2 and WezTerm. If a width and/or height in cells are
+    /// given, the terminal will scale the image to them
+    pub fn serialize_iterm2(
+        &self,
+        width_in_cells: Option<usize>,
+        height_in_cells: Option<usize>,
+    ) -> String {
+        iterm2::serialize(self, width_in_cells, height_in_cells)
+    }
+    /// Writes the whole image as an 8-bit RGBA PNG, pixels that are off are fully transparent
+    #[cfg(feature = "png")]
+    pub fn write_png<W: std::io::Write>(&self, writer: W) -> Result<(), &'static str> {
+        png_codec::write_png(self, writer)
+    }
+    /// Encodes the whole image as an 8-bit binary PPM (P6), pixels that are off are black
+    pub fn to_ppm(&self) -> Vec<u8> {
+        netpbm::to_ppm(self)
+    }
+    /// Encodes the whole image as an 8-bit `RGB_ALPHA` PAM (P7), pixels that are off are fully
+    /// transparent
+    pub fn to_pam(&self) -> Vec<u8> {
+        netpbm::to_pam(self)
+    }
+    /// Encodes the whole image as an uncompressed 32-bit BMP with an alpha channel, pixels that
+    /// are off are fully transparent
+    pub fn to_bmp(&self) -> Vec<u8> {
+        bmp::to_bmp(self)
+    }
+    /// Returns the pixels of this image as 8-bit RGBA data (4 bytes per pixel, row by row), pixels
+    /// that are off are fully transparent
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let (height, width) = self.pixel_size();
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                match self.rgb_at(x, y) {
+                    Some((r, g, b)) => rgba.extend_from_slice(&[r, g, b, 255]),
+                    None => rgba.extend_from_slice(&[0, 0, 0, 0]),
+                }
+            }
+        }
+        rgba
     }
     /// Serializes a specific rectangle of this image without manipulating the image itself, x/y
     /// coordinates as well as width height are in pixels
@@ -114,9 +348,94 @@ impl SixelImage {
         });
         let sixel_serializer =
             SixelSerializer::new(&self.dcs, &adjusted_ra, &self.color_registers, &self.pixels);
-        let serialized_image =
-            sixel_serializer.serialize_range(start_x_index, start_y_index, width, height);
-        serialized_image
+        sixel_serializer.serialize_range(start_x_index, start_y_index, width, height)
+    }
+    /// Serializes the whole image, treating all pixels inside the `mask` rectangles as if they
+    /// were cut out (see [`SixelImage::cut_out`]) without manipulating the image itself
+    pub fn serialize_with_mask(&self, mask: &[Rect]) -> String {
+        let sixel_serializer =
+            SixelSerializer::new(&self.dcs, &self.ra, &self.color_registers, &self.pixels)
+                .mask(mask);
+        sixel_serializer.serialize()
+    }
+    /// Creates a [`SixelBandCache`] out of the current state of this image, for when the same
+    /// image needs to be serialized in many different rectangles (eg. when it's partially covered)
+    pub fn band_cache(&self) -> SixelBandCache {
+        SixelBandCache::new(self)
+    }
+    /// Compares this image to `other` (which must be of the same size) and returns the
+    /// rectangles in which they differ. Pixels are compared by their resolved colors rather than
+    /// by their color register numbers. The rectangles are aligned to sixel bands (they start at
+    /// a multiple of six pixel rows) so that serializing them does not require re-banding
+    pub fn diff(&self, other: &SixelImage) -> Result<Vec<Rect>, &'static str> {
+        if self.pixel_size() != other.pixel_size() {
+            return Err("Cannot diff images of different sizes");
+        }
+        let height = self.pixels.len();
+        let width = std::cmp::max(self.max_row_width(), other.max_row_width());
+        let mut dirty_rects: Vec<Rect> = vec![];
+        for band_start in (0..height).step_by(6) {
+            let band_height = std::cmp::min(6, height - band_start);
+            let mut changed_columns = (0..width).filter(|x| {
+                (band_start..band_start + band_height).any(|y| !self.pixels_match(other, *x, y))
+            });
+            let first_changed_column = match changed_columns.next() {
+                Some(first_changed_column) => first_changed_column,
+                None => continue,
+            };
+            let last_changed_column = changed_columns.next_back().unwrap_or(first_changed_column);
+            let dirty_rect = Rect::new(
+                first_changed_column,
+                band_start,
+                last_changed_column + 1 - first_changed_column,
+                band_height,
+            );
+            match dirty_rects.last_mut() {
+                Some(previous_rect)
+                    if previous_rect.x == dirty_rect.x
+                        && previous_rect.width == dirty_rect.width
+                        && previous_rect.y + previous_rect.height == dirty_rect.y =>
+                {
+                    previous_rect.height += dirty_rect.height;
+                }
+                _ => dirty_rects.push(dirty_rect),
+            }
+        }
+        Ok(dirty_rects)
+    }
+    /// Returns a 64-bit hash of the size of this image and the colors its pixels resolve to, which
+    /// is the same for images that are equal (eg. the same picture drawn with other color register
+    /// numbers, or serialized differently) and is stable across versions and platforms, so that it
+    /// can be used to deduplicate and cache images
+    pub fn content_hash(&self) -> u64 {
+        comparison::content_hash(self)
+    }
+    /// Measures how much this image differs from `other` (which must be of the same size), by
+    /// the perceived difference between the colors each of their pixels resolve to
+    pub fn difference(&self, other: &SixelImage) -> Result<ImageDifference, &'static str> {
+        comparison::difference(self, other)
+    }
+    /// Creates an image showing where this image differs from `other` (which must be of the same
+    /// size): differing pixels are red, identical pixels are a faded gray version of this image
+    pub fn diff_image(&self, other: &SixelImage) -> Result<SixelImage, &'static str> {
+        comparison::diff_image(self, other)
+    }
+    /// Serializes only the regions of this image that changed since `previous` (see
+    /// [`SixelImage::diff`]), returning each of them along with the rectangle it should be drawn
+    /// at. Pixels that were turned off since `previous` are serialized as off and so will only be
+    /// cleared by terminals that do not draw with a transparent background
+    pub fn serialize_diff(
+        &self,
+        previous: &SixelImage,
+    ) -> Result<Vec<(Rect, String)>, &'static str> {
+        let dirty_rects = self.diff(previous)?;
+        Ok(dirty_rects
+            .into_iter()
+            .map(|rect| {
+                let serialized = self.serialize_range(rect.x, rect.y, rect.width, rect.height);
+                (rect, serialized)
+            })
+            .collect())
     }
     /// Manipulates the image in-place, cutting out a rectangle with the specified coordinates. If
     /// the rectangle exceeds the image, it will be partially cut out. All x/y and width/height
@@ -134,9 +453,485 @@ impl SixelImage {
             }
         }
     }
+    /// Manipulates the image in-place, removing its top `count` rows of pixels (eg. when the image
+    /// is scrolled up and partially off-screen). The remaining rows are realigned so that
+    /// serialization starts a fresh sixel band at what used to be row `count`, and the raster
+    /// attributes (if any) are shortened accordingly
+    pub fn drop_top_rows(&mut self, count: usize) {
+        let rows_to_drop = std::cmp::min(count, self.pixels.len());
+        self.pixels.drain(..rows_to_drop);
+        if let Some(ra) = self.ra.as_mut() {
+            ra.pv = ra.pv.map(|pv| pv.saturating_sub(count));
+        }
+    }
+    /// Manipulates the image in-place, keeping only the rectangle with the specified coordinates.
+    /// If the rectangle exceeds the image, only its part inside the image is kept. The raster
+    /// attributes (if any) are adjusted to the new size. All x/y and width/height coordinates are
+    /// in pixels
+    pub fn crop(
+        &mut self,
+        start_x_index: usize,
+        start_y_index: usize,
+        width: usize,
+        height: usize,
+    ) {
+        let rows_to_skip = std::cmp::min(start_y_index, self.pixels.len());
+        self.pixels.drain(..rows_to_skip);
+        self.pixels.truncate(height);
+        for row in self.pixels.iter_mut() {
+            let columns_to_skip = std::cmp::min(start_x_index, row.len());
+            row.drain(..columns_to_skip);
+            row.truncate(width);
+        }
+        let (cropped_height, cropped_width) = self.pixel_size();
+        if let Some(ra) = self.ra.as_mut() {
+            ra.ph = Some(cropped_width);
+            ra.pv = Some(cropped_height);
+        }
+    }
+    /// Manipulates the image in-place, scaling it to `width` x `height` pixels with
+    /// nearest-neighbor sampling so that no new colors are introduced. The raster attributes (if
+    /// any) are adjusted to the new size
+    pub fn scale(&mut self, width: usize, height: usize) {
+        let (original_height, original_width) = self.pixel_size();
+        let off_pixel = Pixel {
+            on: false,
+            color: 0,
+        };
+        let scaled_pixels = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let source_x = x * original_width / width;
+                        let source_y = y * original_height / height;
+                        self.pixel_at(source_x, source_y)
+                            .copied()
+                            .unwrap_or(off_pixel)
+                    })
+                    .collect()
+            })
+            .collect();
+        self.pixels = scaled_pixels;
+        if let Some(ra) = self.ra.as_mut() {
+            ra.ph = Some(width);
+            ra.pv = Some(height);
+        }
+    }
+    /// Returns the color that pixels drawn with `color_register` have, falling back to the VT340
+    /// defaults (or black) if it was never defined
+    pub fn register_color(&self, color_register: u16) -> SixelColor {
+        resolve_register_color(&self.color_registers, color_register)
+    }
+    /// Returns the 8-bit RGB color of the pixel at the given coordinates (in pixels), or `None`
+    /// if it is off or outside the image
+    pub fn rgb_at(&self, x: usize, y: usize) -> Option<(u8, u8, u8)> {
+        self.pixel_at(x, y)
+            .filter(|pixel| pixel.on)
+            .map(|pixel| self.register_color(pixel.color).to_rgb8())
+    }
+    /// Renders the image as text to be displayed on terminals that do not support sixel, scaled
+    /// to fill a grid of `columns` x `rows` cells. Returns one string per row of cells
+    pub fn render_text(&self, columns: usize, rows: usize, mode: TextRenderMode) -> Vec<String> {
+        TextRenderer::new(self, columns, rows).render(mode)
+    }
+    /// Returns the smallest rectangle containing all pixels that are on, or `None` if there are
+    /// none. If a `background` color register is given, pixels drawn with it are treated as if
+    /// they were off
+    pub fn bounding_box(&self, background: Option<u16>) -> Option<Rect> {
+        let is_painted = |pixel: &&Pixel| pixel.on && background != Some(pixel.color);
+        let mut bounds: Option<(usize, usize, usize, usize)> = None; // left, top, right, bottom
+        let (_, width) = self.pixel_size();
+        for (y, row) in self.pixels.iter().enumerate() {
+            let mut painted_columns = row
+                .iter()
+                .take(width)
+                .enumerate()
+                .filter(|(_, pixel)| is_painted(pixel))
+                .map(|(x, _)| x);
+            let first_painted_column = match painted_columns.next() {
+                Some(first_painted_column) => first_painted_column,
+                None => continue,
+            };
+            let last_painted_column = painted_columns.next_back().unwrap_or(first_painted_column);
+            bounds = Some(match bounds {
+                Some((left, top, right, _)) => (
+                    std::cmp::min(left, first_painted_column),
+                    top,
+                    std::cmp::max(right, last_painted_column),
+                    y,
+                ),
+                None => (first_painted_column, y, last_painted_column, y),
+            });
+        }
+        bounds.map(|(left, top, right, bottom)| {
+            Rect::new(left, top, right + 1 - left, bottom + 1 - top)
+        })
+    }
+    /// Manipulates the image in-place, cropping it to its [`SixelImage::bounding_box`] (with the
+    /// same optional `background` color register) and adjusting the raster attributes (if any).
+    /// Returns the rectangle of the original image that was kept, an image with no painted pixels
+    /// is cropped to nothing
+    pub fn trim(&mut self, background: Option<u16>) -> Option<Rect> {
+        let bounding_box = self.bounding_box(background);
+        let kept = bounding_box.unwrap_or(Rect::new(0, 0, 0, 0));
+        self.crop(kept.x, kept.y, kept.width, kept.height);
+        bounding_box
+    }
+    /// Counts how the pixels of this image use its color registers. Pixels missing from rows
+    /// shorter than the image width are counted as off
+    pub fn stats(&self) -> ImageStats {
+        let (height, width) = self.pixel_size();
+        let mut register_pixel_counts = BTreeMap::new();
+        let mut off_pixels = 0;
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = match self.pixel_at(x, y).filter(|pixel| pixel.on) {
+                    Some(pixel) => pixel,
+                    None => {
+                        off_pixels += 1;
+                        continue;
+                    }
+                };
+                *register_pixel_counts.entry(pixel.color).or_insert(0) += 1;
+            }
+        }
+        ImageStats {
+            defined_registers: self.color_registers.len(),
+            used_registers: register_pixel_counts.len(),
+            register_pixel_counts,
+            off_pixels,
+            painted_bounds: self.bounding_box(None),
+            is_fully_opaque: off_pixels == 0,
+        }
+    }
+    /// Returns a color register drawing with `color`: an existing register with the same 8-bit
+    /// RGB color, or the lowest free register below the register limit (see
+    /// [`SixelImage::set_register_limit`]) which is then defined as `color`. Registers pixels
+    /// already draw with are never free, even if the image relies on their default color. When no
+    /// register is free, the register with the closest color is returned instead
+    pub fn register_for(&mut self, color: SixelColor) -> u16 {
+        let rgb = color.to_rgb8();
+        let mut taken_registers = self.used_registers();
+        taken_registers.extend(self.color_registers.keys());
+        if let Some(color_register) = taken_registers
+            .iter()
+            .find(|color_register| self.register_color(**color_register).to_rgb8() == rgb)
+        {
+            return *color_register;
+        }
+        let register_limit = std::cmp::min(self.register_limit, u16::MAX as usize + 1);
+        let free_register = (0..register_limit)
+            .map(|color_register| color_register as u16)
+            .find(|color_register| !taken_registers.contains(color_register));
+        match free_register {
+            Some(color_register) => {
+                self.color_registers.insert(color_register, color);
+                color_register
+            }
+            None => taken_registers
+                .iter()
+                .min_by_key(|color_register| {
+                    color_distance(self.register_color(**color_register).to_rgb8(), rgb)
+                })
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+    /// Returns the number of color registers [`SixelImage::register_for`] allocates new colors
+    /// in, 256 unless changed
+    pub fn register_limit(&self) -> usize {
+        self.register_limit
+    }
+    /// Sets the number of color registers [`SixelImage::register_for`] allocates new colors in
+    /// (registers 0 up to `register_limit`), eg. to match the number of registers a terminal
+    /// supports. Registers the image already defines are kept
+    pub fn set_register_limit(&mut self, register_limit: usize) {
+        self.register_limit = register_limit;
+    }
+    /// Manipulates the image in-place, replacing the color of every color register with the one
+    /// `remap` returns for it (eg. to invert an image for a dark terminal theme). Registers that
+    /// pixels are drawn with but were never defined are remapped from their default colors
+    pub fn remap_colors(&mut self, mut remap: impl FnMut(SixelColor) -> SixelColor) {
+        for color_register in self.used_registers() {
+            if !self.color_registers.contains_key(&color_register) {
+                let default_color = self.register_color(color_register);
+                self.color_registers.insert(color_register, default_color);
+            }
+        }
+        for color in self.color_registers.values_mut() {
+            *color = remap(*color);
+        }
+    }
+    /// Manipulates the image in-place, replacing its color registers with `palette` (registers 0
+    /// up to the palette length) and drawing each pixel with the palette color closest to its
+    /// original color, eg. to map the image onto a terminal's fixed 16-color palette
+    pub fn replace_palette(&mut self, palette: &[SixelColor]) -> Result<(), &'static str> {
+        if palette.is_empty() {
+            return Err("Cannot replace the palette with an empty one");
+        }
+        if palette.len() > u16::MAX as usize + 1 {
+            return Err("Palette has more colors than there are color registers");
+        }
+        let palette_rgb: Vec<(u8, u8, u8)> = palette.iter().map(|color| color.to_rgb8()).collect();
+        let mut closest_colors: BTreeMap<u16, u16> = BTreeMap::new();
+        for row in self.pixels.iter_mut() {
+            for pixel in row.iter_mut().filter(|pixel| pixel.on) {
+                let closest_color = *closest_colors.entry(pixel.color).or_insert_with(|| {
+                    let rgb = resolve_register_color(&self.color_registers, pixel.color).to_rgb8();
+                    (0..palette_rgb.len())
+                        .min_by_key(|index| color_distance(palette_rgb[*index], rgb))
+                        .unwrap_or(0) as u16
+                });
+                pixel.color = closest_color;
+            }
+        }
+        self.color_registers = palette
+            .iter()
+            .enumerate()
+            .map(|(index, color)| (index as u16, *color))
+            .collect();
+        Ok(())
+    }
+    /// Returns a grayscale copy of this image, with one color register per gray level actually
+    /// used (numbered in order of first appearance)
+    pub fn to_grayscale(&self) -> SixelImage {
+        self.map_pixel_colors(|rgb| {
+            let gray = ((luminance(rgb) as u16 * 100 + 127) / 255) as u8;
+            Some(SixelColor::Rgb(gray, gray, gray))
+        })
+    }
+    /// Returns a two-color copy of this image for monochrome sixel devices: pixels with a
+    /// perceived brightness (0-255) of at least `threshold` stay on and are drawn white with
+    /// color register 0, all others are turned off. See [`SixelImage::serialize_monochrome`]
+    pub fn to_monochrome(&self, threshold: u8) -> SixelImage {
+        self.map_pixel_colors(|rgb| {
+            if luminance(rgb) >= threshold {
+                Some(SixelColor::Rgb(100, 100, 100))
+            } else {
+                None
+            }
+        })
+    }
+    /// Manipulates the image in-place, painting every pixel inside `rect` with `color`
+    pub fn fill_rect(&mut self, rect: Rect, color: impl Into<PaintColor>) {
+        let color_register = self.paint_color_register(color.into());
+        drawing::fill_rect(self, rect, color_register);
+    }
+    /// Manipulates the image in-place, painting the one pixel wide border of `rect` with `color`
+    pub fn draw_rect_outline(&mut self, rect: Rect, color: impl Into<PaintColor>) {
+        let color_register = self.paint_color_register(color.into());
+        drawing::draw_rect_outline(self, rect, color_register);
+    }
+    /// Manipulates the image in-place, painting a one pixel wide line between the `from` and `to`
+    /// (x, y) coordinates (both included) with `color`
+    pub fn draw_line(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        color: impl Into<PaintColor>,
+    ) {
+        let color_register = self.paint_color_register(color.into());
+        drawing::draw_line(self, from, to, color_register);
+    }
+    /// Manipulates the image in-place, painting the area around the pixel at `(x, y)` with
+    /// `color`. The area consists of the pixels connected to it horizontally or vertically that
+    /// are either all off, or all drawn with the same color register
+    pub fn flood_fill(&mut self, x: usize, y: usize, color: impl Into<PaintColor>) {
+        let color_register = self.paint_color_register(color.into());
+        drawing::flood_fill(self, x, y, color_register);
+    }
+    // the length of the longest row, rows of deserialized images can be longer than the first one
+    pub(crate) fn max_row_width(&self) -> usize {
+        self.pixels.iter().map(Vec::len).max().unwrap_or(0)
+    }
+    fn pixel_at(&self, x: usize, y: usize) -> Option<&Pixel> {
+        self.pixels.get(y).and_then(|row| row.get(x))
+    }
+    fn pixels_match(&self, other: &SixelImage, x: usize, y: usize) -> bool {
+        self.rgb_at(x, y) == other.rgb_at(x, y)
+    }
+    // a copy of this image with the color of every pixel that is on replaced by `map` (which
+    // turns the pixel off if it returns `None`), using one color register per resulting color
+    fn map_pixel_colors(&self, map: impl Fn((u8, u8, u8)) -> Option<SixelColor>) -> SixelImage {
+        let mut mapped_colors: BTreeMap<u16, Option<SixelColor>> = BTreeMap::new();
+        let mut color_registers: Vec<SixelColor> = vec![];
+        let mut mapped_image = self.clone();
+        for pixel in mapped_image.pixels.iter_mut().flatten() {
+            if !pixel.on {
+                continue;
+            }
+            let mapped_color = *mapped_colors
+                .entry(pixel.color)
+                .or_insert_with(|| map(self.register_color(pixel.color).to_rgb8()));
+            match mapped_color {
+                Some(color) => {
+                    let color_register = match color_registers.iter().position(|c| *c == color) {
+                        Some(color_register) => color_register,
+                        None => {
+                            color_registers.push(color);
+                            color_registers.len() - 1
+                        }
+                    };
+                    pixel.color = color_register as u16;
+                }
+                None => {
+                    pixel.on = false;
+                    pixel.color = 0;
+                }
+            }
+        }
+        mapped_image.color_registers = color_registers
+            .into_iter()
+            .enumerate()
+            .map(|(color_register, color)| (color_register as u16, color))
+            .collect();
+        mapped_image
+    }
+    // the registers of all pixels that are on
+    fn used_registers(&self) -> BTreeSet<u16> {
+        self.pixels
+            .iter()
+            .flatten()
+            .filter(|pixel| pixel.on)
+            .map(|pixel| pixel.color)
+            .collect()
+    }
+    // the color register to paint with, see `SixelImage::register_for`
+    fn paint_color_register(&mut self, paint_color: PaintColor) -> u16 {
+        match paint_color {
+            PaintColor::Register(color_register) => color_register,
+            PaintColor::Color(color) => self.register_for(color),
+        }
+    }
+}
+
+/// Images are equal if they are of the same size and each of their pixels is either off in both or
+/// resolves to the same 8-bit RGB color, regardless of the color registers they are drawn with
+/// and of their DCS and raster attributes
+impl PartialEq for SixelImage {
+    fn eq(&self, other: &Self) -> bool {
+        comparison::images_equal(self, other)
+    }
+}
+
+impl Eq for SixelImage {}
+
+impl Hash for SixelImage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.content_hash());
+    }
+}
+
+impl DCS {
+    /// The macro parameter (P1) of the device control string, which selects a pixel aspect ratio
+    pub fn macro_parameter(&self) -> u8 {
+        self.macro_parameter
+    }
+    /// Whether pixels that are off should be left transparent (P2 = 1) rather than drawn with
+    /// the background color
+    pub fn transparent_bg(&self) -> bool {
+        self.transparent_bg
+    }
+}
+
+impl RA {
+    /// The numerator of the pixel aspect ratio
+    pub fn pan(&self) -> usize {
+        self.pan
+    }
+    /// The denominator of the pixel aspect ratio
+    pub fn pad(&self) -> usize {
+        self.pad
+    }
+    /// The declared width of the image in pixels
+    pub fn ph(&self) -> Option<usize> {
+        self.ph
+    }
+    /// The declared height of the image in pixels
+    pub fn pv(&self) -> Option<usize> {
+        self.pv
+    }
+}
+
+/// Options for importing RGBA pixel data, see [`SixelImage::from_rgba`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RgbaImportOptions {
+    alpha_threshold: u8,
+    blend_background: Option<(u8, u8, u8)>,
+}
+
+impl Default for RgbaImportOptions {
+    fn default() -> Self {
+        RgbaImportOptions {
+            alpha_threshold: 128,
+            blend_background: None,
+        }
+    }
+}
+
+impl RgbaImportOptions {
+    pub fn new() -> Self {
+        RgbaImportOptions::default()
+    }
+    /// Pixels with an alpha lower than `alpha_threshold` are turned off, all others are on. The
+    /// default is 128. A threshold of 0 turns every pixel on, 1 turns off only fully transparent
+    /// pixels
+    pub fn alpha_threshold(mut self, alpha_threshold: u8) -> Self {
+        self.alpha_threshold = alpha_threshold;
+        self
+    }
+    /// Blend the color of partially transparent pixels that are on against this 8-bit RGB
+    /// `background` color (eg. the terminal background), so that anti-aliased edges look right.
+    /// Without a background color, the alpha of pixels that are on is ignored
+    pub fn blend_background(mut self, background: (u8, u8, u8)) -> Self {
+        self.blend_background = Some(background);
+        self
+    }
+}
+
+/// Statistics about the pixels of an image, see [`SixelImage::stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageStats {
+    /// The number of color registers the image defines
+    pub defined_registers: usize,
+    /// The number of color registers at least one pixel is drawn with
+    pub used_registers: usize,
+    /// The number of pixels drawn with each used color register
+    pub register_pixel_counts: BTreeMap<u16, usize>,
+    /// The number of pixels that are off (transparent or drawn with the background color)
+    pub off_pixels: usize,
+    /// The smallest rectangle containing all pixels that are on, `None` if there are none
+    pub painted_bounds: Option<Rect>,
+    /// True if every pixel of the image is on
+    pub is_fully_opaque: bool,
+}
+
+/// A rectangle inside an image, all coordinates are in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+    /// Returns true if the pixel at the given coordinates is inside this rectangle
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
 }
 
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pixel {
     pub on: bool,
     pub color: u16,
@@ -152,12 +947,131 @@ impl fmt::Debug for Pixel {
     }
 }
 
+/// The color to draw with, either an existing color register or a color that will be given a
+/// register of its own if the image does not have one for it yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaintColor {
+    Register(u16),
+    Color(SixelColor),
+}
+
+impl From<u16> for PaintColor {
+    fn from(color_register: u16) -> Self {
+        PaintColor::Register(color_register)
+    }
+}
+
+impl From<SixelColor> for PaintColor {
+    fn from(color: SixelColor) -> Self {
+        PaintColor::Color(color)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SixelColor {
     Rgb(u8, u8, u8),  // 0-100
     Hsl(u16, u8, u8), // 0-360, 0-100, 0-100
 }
 
+// The colors VT340 terminals start with in registers 0-15, used when drawing with a register that
+// was never defined
+const DEFAULT_COLOR_REGISTERS: [SixelColor; 16] = [
+    SixelColor::Rgb(0, 0, 0),
+    SixelColor::Rgb(20, 20, 80),
+    SixelColor::Rgb(80, 13, 13),
+    SixelColor::Rgb(20, 80, 20),
+    SixelColor::Rgb(80, 20, 80),
+    SixelColor::Rgb(20, 80, 80),
+    SixelColor::Rgb(80, 80, 20),
+    SixelColor::Rgb(53, 53, 53),
+    SixelColor::Rgb(26, 26, 26),
+    SixelColor::Rgb(33, 33, 60),
+    SixelColor::Rgb(60, 26, 26),
+    SixelColor::Rgb(33, 60, 33),
+    SixelColor::Rgb(60, 33, 60),
+    SixelColor::Rgb(33, 60, 60),
+    SixelColor::Rgb(60, 60, 33),
+    SixelColor::Rgb(80, 80, 80),
+];
+
+/// The color that pixels drawn with `color_register` have, falling back to the VT340 defaults (or
+/// black) if it is not one of `color_registers`
+pub(crate) fn resolve_register_color(
+    color_registers: &BTreeMap<u16, SixelColor>,
+    color_register: u16,
+) -> SixelColor {
+    color_registers
+        .get(&color_register)
+        .or_else(|| DEFAULT_COLOR_REGISTERS.get(color_register as usize))
+        .copied()
+        .unwrap_or(SixelColor::Rgb(0, 0, 0))
+}
+
+impl SixelColor {
+    /// Converts this color to 8-bit (0-255) RGB components. Note that sixel HLS hues start with
+    /// blue at 0 degrees, followed by red at 120 and green at 240
+    pub fn to_rgb8(&self) -> (u8, u8, u8) {
+        match *self {
+            SixelColor::Rgb(r, g, b) => (percent_to_u8(r), percent_to_u8(g), percent_to_u8(b)),
+            SixelColor::Hsl(h, l, s) => {
+                let hue = ((h as f64 + 240.0) % 360.0) / 60.0;
+                let lightness = std::cmp::min(l, 100) as f64 / 100.0;
+                let saturation = std::cmp::min(s, 100) as f64 / 100.0;
+                let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+                let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+                let (r, g, b) = match hue as u8 {
+                    0 => (chroma, x, 0.0),
+                    1 => (x, chroma, 0.0),
+                    2 => (0.0, chroma, x),
+                    3 => (0.0, x, chroma),
+                    4 => (x, 0.0, chroma),
+                    _ => (chroma, 0.0, x),
+                };
+                let m = lightness - chroma / 2.0;
+                let to_u8 = |component: f64| ((component + m) * 255.0).round() as u8;
+                (to_u8(r), to_u8(g), to_u8(b))
+            }
+        }
+    }
+    // the RGB (percent) color that this color is closest to, the same for colors that resolve to
+    // the same 8-bit RGB color
+    pub(crate) fn to_rgb_percent(self) -> SixelColor {
+        let (r, g, b) = quantizer::to_percent(self.to_rgb8());
+        SixelColor::Rgb(r, g, b)
+    }
+}
+
+fn percent_to_u8(percent: u8) -> u8 {
+    ((std::cmp::min(percent, 100) as u16 * 255 + 50) / 100) as u8
+}
+
+/// The squared euclidean distance between two 8-bit RGB colors
+pub(crate) fn color_distance(first: (u8, u8, u8), second: (u8, u8, u8)) -> u32 {
+    let channel_distance = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel_distance(first.0, second.0)
+        + channel_distance(first.1, second.1)
+        + channel_distance(first.2, second.2)
+}
+
+// an 8-bit RGB color with the given alpha composited over an opaque background color
+fn blend(rgb: (u8, u8, u8), background: (u8, u8, u8), alpha: u8) -> (u8, u8, u8) {
+    let blend_channel = |channel: u8, background_channel: u8| {
+        ((channel as u32 * alpha as u32 + background_channel as u32 * (255 - alpha as u32) + 127)
+            / 255) as u8
+    };
+    (
+        blend_channel(rgb.0, background.0),
+        blend_channel(rgb.1, background.1),
+        blend_channel(rgb.2, background.2),
+    )
+}
+
+/// The perceived brightness (0-255) of an 8-bit RGB color
+pub(crate) fn luminance((r, g, b): (u8, u8, u8)) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
 impl From<ColorCoordinateSystem> for SixelColor {
     fn from(item: ColorCoordinateSystem) -> Self {
         match item {
@@ -0,0 +1,166 @@
+// Reading and writing the binary netpbm formats: PPM (P6) and PAM (P7), see:
+// https://netpbm.sourceforge.net/doc/ppm.html and https://netpbm.sourceforge.net/doc/pam.html
+
+use crate::{RgbaImportOptions, SixelImage};
+
+/// Encodes the image as an 8-bit binary PPM, pixels that are off are black
+pub(crate) fn to_ppm(sixel_image: &SixelImage) -> Vec<u8> {
+    let (height, width) = sixel_image.pixel_size();
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for rgba in sixel_image.to_rgba().chunks_exact(4) {
+        ppm.extend_from_slice(&rgba[..3]);
+    }
+    ppm
+}
+
+/// Encodes the image as an 8-bit RGB_ALPHA PAM, pixels that are off are fully transparent
+pub(crate) fn to_pam(sixel_image: &SixelImage) -> Vec<u8> {
+    let (height, width) = sixel_image.pixel_size();
+    let mut pam = format!(
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        width, height
+    )
+    .into_bytes();
+    pam.extend_from_slice(&sixel_image.to_rgba());
+    pam
+}
+
+pub(crate) fn from_ppm(bytes: &[u8]) -> Result<SixelImage, &'static str> {
+    let mut header = HeaderTokens::new(bytes);
+    if header.next_token() != Some(&b"P6"[..]) {
+        return Err("Not a binary PPM (P6) image");
+    }
+    let width = header.next_number()?;
+    let height = header.next_number()?;
+    let max_value = header.next_number()?;
+    check_size(width, height)?;
+    // a single whitespace character separates the header from the pixel data
+    let data = bytes.get(header.position + 1..).unwrap_or(&[]);
+    let samples = read_samples(
+        data,
+        width.saturating_mul(height).saturating_mul(3),
+        max_value,
+    )?;
+    let rgba: Vec<u8> = samples
+        .chunks_exact(3)
+        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+        .collect();
+    SixelImage::from_rgba(width, height, &rgba, &RgbaImportOptions::default())
+}
+
+pub(crate) fn from_pam(
+    bytes: &[u8],
+    options: &RgbaImportOptions,
+) -> Result<SixelImage, &'static str> {
+    let mut header = HeaderTokens::new(bytes);
+    if header.next_token() != Some(&b"P7"[..]) {
+        return Err("Not a PAM (P7) image");
+    }
+    let (mut width, mut height, mut depth, mut max_value) = (None, None, None, None);
+    let mut tuple_type = None;
+    loop {
+        match header.next_token() {
+            Some(b"WIDTH") => width = Some(header.next_number()?),
+            Some(b"HEIGHT") => height = Some(header.next_number()?),
+            Some(b"DEPTH") => depth = Some(header.next_number()?),
+            Some(b"MAXVAL") => max_value = Some(header.next_number()?),
+            Some(b"TUPLTYPE") => tuple_type = header.next_token(),
+            Some(b"ENDHDR") => break,
+            Some(_) => return Err("Invalid PAM header"),
+            None => return Err("PAM header is missing ENDHDR"),
+        }
+    }
+    let width = width.ok_or("PAM header is missing WIDTH")?;
+    let height = height.ok_or("PAM header is missing HEIGHT")?;
+    let depth = depth.ok_or("PAM header is missing DEPTH")?;
+    let max_value = max_value.ok_or("PAM header is missing MAXVAL")?;
+    check_size(width, height)?;
+    let data = bytes.get(header.position + 1..).unwrap_or(&[]);
+    let samples = read_samples(
+        data,
+        width.saturating_mul(height).saturating_mul(depth),
+        max_value,
+    )?;
+    let has_alpha = match tuple_type {
+        Some(b"RGB_ALPHA") | Some(b"GRAYSCALE_ALPHA") | Some(b"BLACKANDWHITE_ALPHA") => true,
+        _ => depth == 2 || depth == 4,
+    };
+    let rgba: Vec<u8> = match (depth, has_alpha) {
+        (1, false) => samples.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+        (2, true) => samples
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        (3, false) => samples
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        (4, true) => samples,
+        _ => return Err("Unsupported PAM tuple type"),
+    };
+    SixelImage::from_rgba(width, height, &rgba, options)
+}
+
+// with a zero width or height any other size would match the (empty) image data
+fn check_size(width: usize, height: usize) -> Result<(), &'static str> {
+    if width == 0 || height == 0 {
+        return Err("Invalid image size");
+    }
+    Ok(())
+}
+
+// reads `count` samples of up to 16 bits each, scaling them to 8 bits
+fn read_samples(data: &[u8], count: usize, max_value: usize) -> Result<Vec<u8>, &'static str> {
+    if max_value == 0 || max_value > 65535 {
+        return Err("Invalid maximum sample value");
+    }
+    let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+    let data = count
+        .checked_mul(bytes_per_sample)
+        .and_then(|data_length| data.get(..data_length))
+        .ok_or("Image data is shorter than its size")?;
+    let scale = |sample: usize| ((sample * 255 + max_value / 2) / max_value) as u8;
+    Ok(data
+        .chunks_exact(bytes_per_sample)
+        .map(|sample| match sample {
+            [byte] => scale(*byte as usize),
+            _ => scale(((sample[0] as usize) << 8) | sample[1] as usize),
+        })
+        .collect())
+}
+
+// whitespace separated header tokens, skipping comments
+struct HeaderTokens<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> HeaderTokens<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        HeaderTokens { bytes, position: 0 }
+    }
+    fn next_token(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.bytes.get(self.position)? {
+                byte if byte.is_ascii_whitespace() => self.position += 1,
+                b'#' => {
+                    while !matches!(self.bytes.get(self.position), Some(b'\n') | None) {
+                        self.position += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let token_start = self.position;
+        while matches!(self.bytes.get(self.position), Some(byte) if !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        Some(&self.bytes[token_start..self.position])
+    }
+    fn next_number(&mut self) -> Result<usize, &'static str> {
+        self.next_token()
+            .and_then(|token| std::str::from_utf8(token).ok())
+            .and_then(|token| token.parse().ok())
+            .ok_or("Invalid image header")
+    }
+}
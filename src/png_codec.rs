@@ -0,0 +1,61 @@
+use std::io::{Read, Write};
+
+use crate::{RgbaImportOptions, SixelImage};
+
+/// Encodes the image as an 8-bit RGBA PNG, pixels that are off are fully transparent
+pub(crate) fn write_png<W: Write>(sixel_image: &SixelImage, writer: W) -> Result<(), &'static str> {
+    let (height, width) = sixel_image.pixel_size();
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png_writer = encoder
+        .write_header()
+        .map_err(|_| "Failed to write PNG header")?;
+    png_writer
+        .write_image_data(&sixel_image.to_rgba())
+        .map_err(|_| "Failed to write PNG data")?;
+    png_writer.finish().map_err(|_| "Failed to write PNG data")
+}
+
+/// Decodes the first frame of a PNG of any color type and bit depth, quantizing its colors (see
+/// [`SixelImage::from_rgba`])
+pub(crate) fn from_png<R: Read>(
+    reader: R,
+    options: &RgbaImportOptions,
+) -> Result<SixelImage, &'static str> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut png_reader = decoder
+        .read_info()
+        .map_err(|_| "Failed to read PNG header")?;
+    let mut buffer = vec![0; png_reader.output_buffer_size()];
+    let output_info = png_reader
+        .next_frame(&mut buffer)
+        .map_err(|_| "Failed to read PNG data")?;
+    let width = output_info.width as usize;
+    let height = output_info.height as usize;
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in buffer.chunks(output_info.line_size).take(height) {
+        match output_info.color_type {
+            png::ColorType::Rgba => rgba.extend_from_slice(&row[..width * 4]),
+            png::ColorType::Rgb => {
+                for rgb in row[..width * 3].chunks_exact(3) {
+                    rgba.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+                }
+            }
+            png::ColorType::GrayscaleAlpha => {
+                for gray_alpha in row[..width * 2].chunks_exact(2) {
+                    let (gray, alpha) = (gray_alpha[0], gray_alpha[1]);
+                    rgba.extend_from_slice(&[gray, gray, gray, alpha]);
+                }
+            }
+            png::ColorType::Grayscale => {
+                for gray in &row[..width] {
+                    rgba.extend_from_slice(&[*gray, *gray, *gray, 255]);
+                }
+            }
+            png::ColorType::Indexed => return Err("Failed to expand indexed PNG colors"),
+        }
+    }
+    SixelImage::from_rgba(width, height, &rgba, options)
+}
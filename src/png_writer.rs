@@ -0,0 +1,210 @@
+// A dependency-free PNG writer for 8-bit RGBA data. The image data is compressed with LZ77 and the
+// fixed deflate Huffman codes, which is simple but works well for the long runs of identical
+// pixels and repeated rows that sixel images are made of.
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const WINDOW_SIZE: usize = 1 << 15;
+const HASH_SIZE: usize = 1 << 15;
+const MAX_CHAIN_LENGTH: usize = 32;
+const MIN_MATCH_LENGTH: usize = 3;
+const MAX_MATCH_LENGTH: usize = 258;
+
+// the base values and number of extra bits of the deflate length and distance codes
+const LENGTH_BASES: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+pub(crate) fn encode_rgba(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]); // 8 bit depth, RGBA, deflate, no filter, no interlace
+    write_chunk(&mut png, b"IHDR", &header);
+    let mut scanlines = Vec::with_capacity(height * (width * 4 + 1));
+    for y in 0..height {
+        scanlines.push(0); // no filtering
+        scanlines.extend_from_slice(&rgba[y * width * 4..(y + 1) * width * 4]);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let chunk_start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[chunk_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter {
+        bytes: vec![0x78, 0x01], // deflate with a 32K window, no preset dictionary
+        buffer: 0,
+        count: 0,
+    };
+    bits.write(1, 1); // the final block
+    bits.write(1, 2); // compressed with the fixed Huffman codes
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = longest_match(data, position, &head, &prev);
+        if length >= MIN_MATCH_LENGTH {
+            bits.write_length_and_distance(length, distance);
+            for matched_position in position..position + length {
+                insert(data, matched_position, &mut head, &mut prev);
+            }
+            position += length;
+        } else {
+            bits.write_symbol(data[position] as u16);
+            insert(data, position, &mut head, &mut prev);
+            position += 1;
+        }
+    }
+    bits.write_symbol(256); // the end of the block
+    let mut zlib = bits.finish();
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let key = (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize;
+    (key.wrapping_mul(2_654_435_761) >> 8) % HASH_SIZE
+}
+
+// remembers `position` as the latest position of the 3 bytes starting there
+fn insert(data: &[u8], position: usize, head: &mut [usize], prev: &mut [usize]) {
+    if position + MIN_MATCH_LENGTH <= data.len() {
+        let hash = hash(&data[position..]);
+        prev[position % WINDOW_SIZE] = head[hash];
+        head[hash] = position;
+    }
+}
+
+// the (length, distance) of the longest earlier match of the bytes at `position`
+fn longest_match(data: &[u8], position: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if position + MIN_MATCH_LENGTH > data.len() {
+        return (0, 0);
+    }
+    let max_length = std::cmp::min(MAX_MATCH_LENGTH, data.len() - position);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(&data[position..])];
+    for _ in 0..MAX_CHAIN_LENGTH {
+        if candidate == usize::MAX || position - candidate > WINDOW_SIZE - 1 {
+            break;
+        }
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            best_length = length;
+            best_distance = position - candidate;
+            if length == max_length {
+                break;
+            }
+        }
+        let next_candidate = prev[candidate % WINDOW_SIZE];
+        if next_candidate >= candidate {
+            break; // the slot was overwritten by a later position
+        }
+        candidate = next_candidate;
+    }
+    (best_length, best_distance)
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    // writes the `count` lowest bits of `value`, least significant bit first
+    fn write(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+    // Huffman codes are written most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+    // writes a literal/length symbol with the fixed Huffman codes
+    fn write_symbol(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+    fn write_length_and_distance(&mut self, length: usize, distance: usize) {
+        let length_code = LENGTH_BASES.partition_point(|base| *base <= length) - 1;
+        self.write_symbol(257 + length_code as u16);
+        self.write(
+            (length - LENGTH_BASES[length_code]) as u32,
+            LENGTH_EXTRA_BITS[length_code],
+        );
+        let distance_code = DISTANCE_BASES.partition_point(|base| *base <= distance) - 1;
+        self.write_code(distance_code as u32, 5);
+        self.write(
+            (distance - DISTANCE_BASES[distance_code]) as u32,
+            DISTANCE_EXTRA_BITS[distance_code],
+        );
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use crate::SixelColor;
+
+type Rgb = (u8, u8, u8);
+
+/// Reduces a set of colors to a palette of at most `max_colors` sixel colors using median cut.
+///
+/// Colors are first reduced to the percent precision of sixel RGB colors, so images that only
+/// differ in ways sixel cannot represent do not needlessly exhaust the palette.
+pub(crate) struct Quantizer {
+    color_counts: HashMap<Rgb, usize>,
+    color_order: Vec<Rgb>, // the order colors were first seen in, so that palettes are stable
+}
+
+pub(crate) struct Palette {
+    pub colors: Vec<SixelColor>,
+    color_indices: HashMap<Rgb, u16>,
+}
+
+impl Palette {
+    /// The index (in `colors`) of the palette color representing this 8-bit RGB color, which must
+    /// have been added to the quantizer that created this palette
+    pub fn index_of(&self, rgb: Rgb) -> u16 {
+        self.color_indices
+            .get(&to_percent(rgb))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl Quantizer {
+    pub fn new() -> Self {
+        Quantizer {
+            color_counts: HashMap::new(),
+            color_order: vec![],
+        }
+    }
+    pub fn add(&mut self, rgb: Rgb) {
+        let color = to_percent(rgb);
+        let count = self.color_counts.entry(color).or_insert(0);
+        if *count == 0 {
+            self.color_order.push(color);
+        }
+        *count += 1;
+    }
+    pub fn palette(&self, max_colors: usize) -> Palette {
+        let mut color_indices = HashMap::new();
+        if self.color_order.len() <= max_colors {
+            let colors = self
+                .color_order
+                .iter()
+                .enumerate()
+                .map(|(index, color)| {
+                    color_indices.insert(*color, index as u16);
+                    SixelColor::Rgb(color.0, color.1, color.2)
+                })
+                .collect();
+            return Palette {
+                colors,
+                color_indices,
+            };
+        }
+        let mut color_boxes = vec![ColorBox::new(
+            self.color_order
+                .iter()
+                .map(|color| (*color, self.color_counts[color]))
+                .collect(),
+        )];
+        while color_boxes.len() < max_colors {
+            let box_to_split = color_boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, color_box)| color_box.colors.len() > 1)
+                .max_by_key(|(_, color_box)| (color_box.widest_range.1, color_box.pixel_count))
+                .map(|(index, _)| index);
+            match box_to_split {
+                Some(index) => {
+                    let color_box = color_boxes.swap_remove(index);
+                    let (first_half, second_half) = color_box.split();
+                    color_boxes.push(first_half);
+                    color_boxes.push(second_half);
+                }
+                None => break,
+            }
+        }
+        let colors = color_boxes
+            .iter()
+            .enumerate()
+            .map(|(index, color_box)| {
+                for (color, _) in &color_box.colors {
+                    color_indices.insert(*color, index as u16);
+                }
+                let (r, g, b) = color_box.average();
+                SixelColor::Rgb(r, g, b)
+            })
+            .collect();
+        Palette {
+            colors,
+            color_indices,
+        }
+    }
+}
+
+struct ColorBox {
+    colors: Vec<(Rgb, usize)>, // color in percent and the number of pixels with it
+    // cached when the box is created, since they are compared for every box on every split
+    widest_range: (usize, u8),
+    pixel_count: usize,
+}
+
+impl ColorBox {
+    fn new(colors: Vec<(Rgb, usize)>) -> Self {
+        ColorBox {
+            widest_range: widest_range(&colors),
+            pixel_count: colors.iter().map(|(_, count)| count).sum(),
+            colors,
+        }
+    }
+    // splits the box at the median pixel of its widest channel, both halves are never empty
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_range;
+        self.colors
+            .sort_by_key(|(color, _)| (channel_of(*color, channel), *color));
+        let half_pixel_count = self.pixel_count / 2;
+        let mut pixels_so_far = 0;
+        let mut split_index = 1;
+        for (index, (_, count)) in self.colors.iter().enumerate() {
+            pixels_so_far += count;
+            if pixels_so_far >= half_pixel_count {
+                split_index = index + 1;
+                break;
+            }
+        }
+        let split_index = split_index.clamp(1, self.colors.len() - 1);
+        let second_half = self.colors.split_off(split_index);
+        (ColorBox::new(self.colors), ColorBox::new(second_half))
+    }
+    fn average(&self) -> Rgb {
+        let pixel_count = std::cmp::max(self.pixel_count, 1);
+        let mut sums = [0; 3];
+        for (color, count) in &self.colors {
+            for (channel, sum) in sums.iter_mut().enumerate() {
+                *sum += channel_of(*color, channel) as usize * count;
+            }
+        }
+        let average = |sum: usize| ((sum + pixel_count / 2) / pixel_count) as u8;
+        (average(sums[0]), average(sums[1]), average(sums[2]))
+    }
+}
+
+// the channel (0, 1 or 2 for r, g, b) with the widest range among these colors, and that range
+fn widest_range(colors: &[(Rgb, usize)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| channel_of(*color, channel));
+            let min = values.clone().min().unwrap_or(0);
+            let max = values.max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+        .unwrap_or((0, 0))
+}
+
+fn channel_of(color: Rgb, channel: usize) -> u8 {
+    match channel {
+        0 => color.0,
+        1 => color.1,
+        _ => color.2,
+    }
+}
+
+pub(crate) fn to_percent((r, g, b): Rgb) -> Rgb {
+    let percent = |component: u8| ((component as u16 * 100 + 127) / 255) as u8;
+    (percent(r), percent(g), percent(b))
+}
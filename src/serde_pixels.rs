@@ -0,0 +1,58 @@
+// A compact serde representation of pixel rows: each row is a list of runs of identical pixels,
+// stored as (count, on, color) tuples rather than one entry per pixel
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Pixel;
+
+type PixelRun = (usize, bool, u16);
+
+// runs are expanded into one pixel each, so that a few bytes of untrusted input cannot make
+// deserialization allocate without bounds (an 8192x8192 image is still accepted)
+const MAX_PIXELS: usize = 1 << 26;
+
+pub(crate) fn serialize<S: Serializer>(
+    pixels: &[Vec<Pixel>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let rows: Vec<Vec<PixelRun>> = pixels
+        .iter()
+        .map(|row| {
+            let mut runs: Vec<PixelRun> = vec![];
+            for pixel in row {
+                match runs.last_mut() {
+                    Some((count, on, color)) if *on == pixel.on && *color == pixel.color => {
+                        *count += 1
+                    }
+                    _ => runs.push((1, pixel.on, pixel.color)),
+                }
+            }
+            runs
+        })
+        .collect();
+    rows.serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<Pixel>>, D::Error> {
+    let rows: Vec<Vec<PixelRun>> = Vec::deserialize(deserializer)?;
+    let mut pixel_count: usize = 0;
+    for (count, _, _) in rows.iter().flatten() {
+        pixel_count = pixel_count
+            .checked_add(*count)
+            .filter(|pixel_count| *pixel_count <= MAX_PIXELS)
+            .ok_or_else(|| D::Error::custom("too many pixels"))?;
+    }
+    Ok(rows
+        .into_iter()
+        .map(|runs| {
+            let mut row = vec![];
+            for (count, on, color) in runs {
+                row.resize(row.len() + count, Pixel { on, color });
+            }
+            row
+        })
+        .collect())
+}
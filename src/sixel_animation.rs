@@ -0,0 +1,103 @@
+use sixel_tokenizer::Parser;
+use std::time::Duration;
+
+use crate::{Rect, SixelDeserializer, SixelImage};
+
+/// A sequence of [`SixelImage`] frames, as emitted by programs that animate by repeatedly drawing
+/// sixel images at the same cursor position.
+///
+/// Bytes are parsed "on the wire" with [`SixelAnimation::advance`], and every image that ends in
+/// them is added as a new frame along with the timestamp it was received at.
+#[derive(Debug, Clone)]
+pub struct SixelAnimation {
+    parser: Parser,
+    sixel_deserializer: SixelDeserializer,
+    frames: Vec<SixelFrame>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SixelFrame {
+    pub image: SixelImage,
+    pub timestamp: Duration, // relative to the start of the stream, as provided by the caller
+}
+
+impl Default for SixelAnimation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SixelAnimation {
+    pub fn new() -> Self {
+        SixelAnimation {
+            parser: Parser::new(),
+            sixel_deserializer: SixelDeserializer::new(),
+            frames: vec![],
+        }
+    }
+    /// Parse the next chunk of the byte stream, adding a frame for every image that ends in it.
+    /// `timestamp` is the time (relative to the start of the stream) these bytes were received.
+    ///
+    /// If the stream is corrupted, the image currently being parsed is discarded and an error is
+    /// returned once the whole chunk was parsed, the next image in the stream will be parsed
+    /// normally
+    pub fn advance(&mut self, bytes: &[u8], timestamp: Duration) -> Result<(), &'static str> {
+        let mut result = Ok(());
+        for byte in bytes {
+            let mut event_result = Ok(());
+            self.parser.advance(byte, |sixel_event| {
+                if event_result.is_ok() {
+                    event_result = self.sixel_deserializer.handle_event(sixel_event);
+                }
+            });
+            if event_result.is_err() {
+                self.sixel_deserializer.reset();
+                result = event_result;
+            } else if self.sixel_deserializer.is_complete() {
+                let image = self.sixel_deserializer.create_image()?;
+                self.frames.push(SixelFrame { image, timestamp });
+            }
+        }
+        result
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn frames(&self) -> &[SixelFrame] {
+        &self.frames
+    }
+    pub fn frame(&self, index: usize) -> Option<&SixelFrame> {
+        self.frames.get(index)
+    }
+    /// Returns how long the frame at `index` was displayed before the next frame replaced it, or
+    /// `None` if this is the last frame (or there is no such frame)
+    pub fn frame_duration(&self, index: usize) -> Option<Duration> {
+        let frame = self.frames.get(index)?;
+        let next_frame = self.frames.get(index + 1)?;
+        Some(next_frame.timestamp.saturating_sub(frame.timestamp))
+    }
+    /// Returns the time between the first and the last frames
+    pub fn total_duration(&self) -> Duration {
+        match (self.frames.first(), self.frames.last()) {
+            (Some(first_frame), Some(last_frame)) => {
+                last_frame.timestamp.saturating_sub(first_frame.timestamp)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+    /// Serializes the whole frame at `index`
+    pub fn serialize_frame(&self, index: usize) -> Option<String> {
+        self.frames.get(index).map(|frame| frame.image.serialize())
+    }
+    /// Serializes only the regions of frame `to_index` that differ from frame `from_index` (see
+    /// [`SixelImage::serialize_diff`]), the frames must be of the same size
+    pub fn serialize_delta(
+        &self,
+        from_index: usize,
+        to_index: usize,
+    ) -> Result<Vec<(Rect, String)>, &'static str> {
+        let from_frame = self.frames.get(from_index).ok_or("No such frame")?;
+        let to_frame = self.frames.get(to_index).ok_or("No such frame")?;
+        to_frame.image.serialize_diff(&from_frame.image)
+    }
+}
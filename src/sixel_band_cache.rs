@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use crate::{Pixel, SixelColor, SixelImage, SixelSerializer, DCS, RA};
+
+/// A precomputed representation of a [`SixelImage`], split into six-pixel-high bands with the
+/// pixels of each color stored as runs of identical sixel bytes.
+///
+/// Serializing a rectangle out of the cache only touches the bands and runs that intersect it,
+/// so it is proportional to the size of the rectangle rather than to the size of the image. The
+/// cache is a snapshot: it needs to be recreated after the image it was created from is changed.
+#[derive(Debug, Clone)]
+pub struct SixelBandCache {
+    dcs: DCS,
+    ra: Option<RA>,
+    color_registers: BTreeMap<u16, SixelColor>,
+    row_lengths: Vec<usize>,
+    bands: Vec<SixelBand>,
+}
+
+#[derive(Debug, Clone)]
+struct SixelBand {
+    color_runs: BTreeMap<u16, Vec<SixelRun>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SixelRun {
+    start: usize,
+    length: usize,
+    byte: u8, // the six bits of this band, without the 0x3f offset
+}
+
+impl SixelBandCache {
+    /// Creates a new cache out of the current state of `sixel_image`
+    pub fn new(sixel_image: &SixelImage) -> Self {
+        let row_lengths = sixel_image.pixels.iter().map(|row| row.len()).collect();
+        let bands = sixel_image.pixels.chunks(6).map(SixelBand::new).collect();
+        SixelBandCache {
+            dcs: sixel_image.dcs.clone(),
+            ra: sixel_image.ra.clone(),
+            color_registers: sixel_image.color_registers.clone(),
+            row_lengths,
+            bands,
+        }
+    }
+    /// Returns the (height, width) in pixels of the image this cache was created from
+    pub fn pixel_size(&self) -> (usize, usize) {
+        let width = self.row_lengths.first().copied().unwrap_or(0);
+        (self.row_lengths.len(), width)
+    }
+    /// Serializes a specific rectangle of the cached image, producing the same output as
+    /// [`SixelImage::serialize_range`]. x/y coordinates as well as width height are in pixels
+    pub fn serialize_range(
+        &self,
+        start_x_index: usize,
+        start_y_index: usize,
+        width: usize,
+        height: usize,
+    ) -> String {
+        let adjusted_ra = self.ra.as_ref().map(|ra| RA {
+            pan: ra.pan,
+            pad: ra.pad,
+            ph: Some(width),
+            pv: Some(height),
+        });
+        let no_pixels = vec![];
+        let sixel_serializer =
+            SixelSerializer::new(&self.dcs, &adjusted_ra, &self.color_registers, &no_pixels);
+        sixel_serializer.serialize_cached_range(self, start_x_index, start_y_index, width, height)
+    }
+    /// The number of pixel lines the serializer should consider when serializing a rectangle of
+    /// this height
+    pub(crate) fn max_lines(&self, height: usize) -> usize {
+        std::cmp::min(height, self.row_lengths.len())
+    }
+    /// Returns the sixel characters of each color in the output band starting at
+    /// `absolute_line_index` along with the length of the line, the same way
+    /// [`SixelSerializer`] would have collected them by scanning the pixels column by column
+    pub(crate) fn band_characters(
+        &self,
+        absolute_line_index: usize,
+        start_x_index: usize,
+        width: usize,
+        max_y_index: usize,
+    ) -> (BTreeMap<u16, String>, usize) {
+        let mut color_index_to_character_string = BTreeMap::new();
+        let rows_in_band = std::cmp::min(max_y_index.saturating_sub(absolute_line_index) + 1, 6);
+        let line_length = if rows_in_band < 6 {
+            // the serializer only stops at the end of the range for partial bands
+            width
+        } else {
+            let band_width = self
+                .row_lengths
+                .iter()
+                .skip(absolute_line_index)
+                .take(6)
+                .max()
+                .copied()
+                .unwrap_or(0);
+            std::cmp::min(band_width, start_x_index + width).saturating_sub(start_x_index)
+        };
+        if line_length == 0 {
+            return (color_index_to_character_string, line_length);
+        }
+        let end_x_index = start_x_index + line_length;
+        let shift = absolute_line_index % 6;
+        let band_index = absolute_line_index / 6;
+        let row_mask = (1u8 << rows_in_band) - 1;
+        let mut color_index_to_bytes: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+        if let Some(band) = self.bands.get(band_index) {
+            band.collect_bytes(
+                &mut color_index_to_bytes,
+                start_x_index,
+                end_x_index,
+                |byte| byte >> shift,
+            );
+        }
+        if shift > 0 {
+            if let Some(band) = self.bands.get(band_index + 1) {
+                band.collect_bytes(
+                    &mut color_index_to_bytes,
+                    start_x_index,
+                    end_x_index,
+                    |byte| (byte << (6 - shift)) & 0x3f,
+                );
+            }
+        }
+        for (color_index, bytes) in color_index_to_bytes {
+            if bytes.iter().all(|byte| byte & row_mask == 0) {
+                continue;
+            }
+            let sixel_chars = bytes
+                .iter()
+                .map(|byte| char::from((byte & row_mask) + 0x3f))
+                .collect();
+            color_index_to_character_string.insert(color_index, sixel_chars);
+        }
+        (color_index_to_character_string, line_length)
+    }
+}
+
+impl SixelBand {
+    fn new(rows: &[Vec<Pixel>]) -> Self {
+        let mut color_runs: BTreeMap<u16, Vec<SixelRun>> = BTreeMap::new();
+        let band_width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut color_index_to_byte: BTreeMap<u16, u8> = BTreeMap::new();
+        for column_index in 0..band_width {
+            color_index_to_byte.clear();
+            for (row_index, row) in rows.iter().enumerate() {
+                if let Some(pixel) = row.get(column_index).filter(|pixel| pixel.on) {
+                    *color_index_to_byte.entry(pixel.color).or_insert(0) |= 1 << row_index;
+                }
+            }
+            for (color_index, byte) in &color_index_to_byte {
+                let runs = color_runs.entry(*color_index).or_default();
+                match runs.last_mut() {
+                    Some(run) if run.byte == *byte && run.start + run.length == column_index => {
+                        run.length += 1;
+                    }
+                    _ => runs.push(SixelRun {
+                        start: column_index,
+                        length: 1,
+                        byte: *byte,
+                    }),
+                }
+            }
+        }
+        SixelBand { color_runs }
+    }
+    fn collect_bytes(
+        &self,
+        color_index_to_bytes: &mut BTreeMap<u16, Vec<u8>>,
+        start_x_index: usize,
+        end_x_index: usize,
+        shift_byte: impl Fn(u8) -> u8,
+    ) {
+        for (color_index, runs) in &self.color_runs {
+            let first_run_index =
+                runs.partition_point(|run| run.start + run.length <= start_x_index);
+            for run in runs[first_run_index..]
+                .iter()
+                .take_while(|run| run.start < end_x_index)
+            {
+                let shifted_byte = shift_byte(run.byte);
+                if shifted_byte == 0 {
+                    continue;
+                }
+                let bytes = color_index_to_bytes
+                    .entry(*color_index)
+                    .or_insert_with(|| vec![0; end_x_index - start_x_index]);
+                let run_start = std::cmp::max(run.start, start_x_index);
+                let run_end = std::cmp::min(run.start + run.length, end_x_index);
+                for byte in &mut bytes[run_start - start_x_index..run_end - start_x_index] {
+                    *byte |= shifted_byte;
+                }
+            }
+        }
+    }
+}
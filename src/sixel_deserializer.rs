@@ -1,8 +1,7 @@
 use sixel_tokenizer::SixelEvent;
 use std::collections::BTreeMap;
-use std::iter;
 
-use crate::{Pixel, SixelColor, SixelImage, DCS, RA};
+use crate::{Pixel, SixelColor, SixelImage, DCS, MAX_COLOR_REGISTERS, RA};
 
 #[derive(Debug, Clone)]
 pub struct SixelDeserializer {
@@ -16,6 +15,13 @@ pub struct SixelDeserializer {
     max_height: Option<usize>,
     stop_parsing: bool,
     got_dcs: bool,
+    got_end: bool,
+}
+
+impl Default for SixelDeserializer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SixelDeserializer {
@@ -34,6 +40,7 @@ impl SixelDeserializer {
             max_height: None,
             stop_parsing: false,
             got_dcs: false,
+            got_end: false,
         }
     }
     /// Provide a `max_height` value in pixels, all pixels beyond this max height will not be
@@ -42,7 +49,22 @@ impl SixelDeserializer {
         self.max_height = Some(max_height);
         self
     }
-    /// Create a new [`SixelImage`] out of the existing state and consume it.
+    /// Returns true once the end of the current image was reached, at which point it should be
+    /// created with [`SixelDeserializer::create_image`] before handling the events of the next
+    /// one
+    pub fn is_complete(&self) -> bool {
+        self.got_end
+    }
+    /// Discard the existing state so that the next event starts a new image, keeping the
+    /// configured `max_height`
+    pub fn reset(&mut self) {
+        *self = SixelDeserializer {
+            max_height: self.max_height,
+            ..SixelDeserializer::new()
+        };
+    }
+    /// Create a new [`SixelImage`] out of the existing state and consume it, resetting the
+    /// deserializer so that it can be used for the next image.
     pub fn create_image(&mut self) -> Result<SixelImage, &'static str> {
         if !self.got_dcs {
             return Err("Corrupted image sequence");
@@ -51,11 +73,13 @@ impl SixelDeserializer {
         let ra = std::mem::take(&mut self.ra);
         let pixels = std::mem::take(&mut self.pixels);
         let color_registers = std::mem::take(&mut self.color_registers);
+        self.reset();
         Ok(SixelImage {
             dcs,
             ra,
             pixels,
             color_registers,
+            register_limit: MAX_COLOR_REGISTERS,
         })
     }
     /// Handle a [`SixelEvent`], changing the internal state to match
@@ -63,7 +87,8 @@ impl SixelDeserializer {
         if !self.got_dcs && !matches!(event, SixelEvent::Dcs { .. }) {
             return Err("Corrupted image sequence");
         }
-        if self.stop_parsing {
+        // the end of an image that was cut short still completes it
+        if self.stop_parsing && !matches!(event, SixelEvent::End) {
             return Ok(());
         }
         match event {
@@ -137,7 +162,9 @@ impl SixelDeserializer {
             SixelEvent::UnknownSequence(_) => {
                 return Err("Corrupted Sixel sequence");
             }
-            SixelEvent::End => {}
+            SixelEvent::End => {
+                self.got_end = true;
+            }
         }
         Ok(())
     }
@@ -148,8 +175,9 @@ impl SixelDeserializer {
         }
     }
     fn add_sixel_byte(&mut self, byte: u8, repeat_count: usize) {
-        let mut pixel_line_index_in_sixel = 0;
-        for bit in SixelPixelIterator::new(byte.saturating_sub(63)) {
+        for (pixel_line_index_in_sixel, bit) in
+            SixelPixelIterator::new(byte.saturating_sub(63)).enumerate()
+        {
             let current_line = self
                 .pixels
                 .get_mut(self.sixel_cursor_y + pixel_line_index_in_sixel)
@@ -169,7 +197,6 @@ impl SixelDeserializer {
                     _ => {} // bit is off and pixel already exists, so noop
                 }
             }
-            pixel_line_index_in_sixel += 1;
         }
     }
     fn pad_lines_vertically(&mut self, pad_until: usize) {
@@ -179,9 +206,7 @@ impl SixelDeserializer {
         };
         if self.pixels.len() < pad_until {
             let empty_line = vec![empty_pixel; pad_until];
-            let lines_to_pad = pad_until - self.pixels.len();
-            let line_padding = iter::repeat(empty_line).take(lines_to_pad);
-            self.pixels.extend(line_padding);
+            self.pixels.resize(pad_until, empty_line);
         }
     }
     fn pad_lines_horizontally(&mut self, pad_until: usize) {
@@ -191,9 +216,7 @@ impl SixelDeserializer {
         };
         for pixel_line in self.pixels.iter_mut() {
             if pixel_line.len() < pad_until {
-                let pixel_count_to_pad = pad_until - pixel_line.len();
-                let pixel_padding = iter::repeat(empty_pixel).take(pixel_count_to_pad);
-                pixel_line.extend(pixel_padding);
+                pixel_line.resize(pad_until, empty_pixel);
             }
         }
     }
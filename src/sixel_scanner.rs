@@ -0,0 +1,175 @@
+use std::ops::Range;
+
+use crate::SixelImage;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+
+/// Walks a general VT byte stream (eg. a recorded terminal session or a log file) and yields
+/// every sixel image embedded in it.
+///
+/// Text, CSI sequences, OSC sequences and non-sixel DCS/SOS/PM/APC strings are skipped over.
+/// Sixel sequences that are not terminated or that fail to parse are skipped as well.
+///
+/// # Example
+/// ```rust
+/// use sixel_image::SixelScanner;
+///
+/// let output = b"some text\x1b[1;31mred\x1b[0m\x1bPq#0;2;100;0;0#0~~\x1b\\more text";
+/// let embedded_images: Vec<_> = SixelScanner::new(output).collect();
+/// assert_eq!(embedded_images.len(), 1);
+/// assert_eq!(embedded_images[0].range, 23..44);
+/// assert_eq!(embedded_images[0].image.pixel_size(), (6, 2));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SixelScanner<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+/// A sixel image found by [`SixelScanner`], `range` are the byte offsets of its serialized form
+/// in the scanned stream, from its DCS up to and including its string terminator
+#[derive(Debug, Clone)]
+pub struct EmbeddedSixelImage {
+    pub image: SixelImage,
+    pub range: Range<usize>,
+}
+
+enum ControlString {
+    Sixel,
+    Other,
+}
+
+impl<'a> SixelScanner<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        SixelScanner { bytes, position: 0 }
+    }
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        self.bytes.get(index).copied()
+    }
+    /// Moves past the escape sequence starting at `self.position` (which must be an ESC),
+    /// returning the range and kind of the control string if this sequence introduced one
+    fn skip_escape_sequence(&mut self) -> Option<(Range<usize>, ControlString)> {
+        let sequence_start = self.position;
+        match self.byte_at(sequence_start + 1) {
+            Some(b'[') => {
+                // CSI: parameter and intermediate bytes followed by a single final byte
+                let final_byte_index = self.bytes[sequence_start + 2..]
+                    .iter()
+                    .position(|byte| (0x40..=0x7e).contains(byte))
+                    .map(|index| sequence_start + 2 + index);
+                self.position = final_byte_index
+                    .map(|index| index + 1)
+                    .unwrap_or(self.bytes.len());
+                None
+            }
+            Some(b']') => {
+                // OSC: terminated either by BEL or by ST
+                self.position = sequence_start + 2;
+                while let Some(byte) = self.byte_at(self.position) {
+                    match byte {
+                        BEL => {
+                            self.position += 1;
+                            break;
+                        }
+                        ESC => {
+                            if self.byte_at(self.position + 1) == Some(b'\\') {
+                                self.position += 2;
+                            }
+                            break;
+                        }
+                        _ => self.position += 1,
+                    }
+                }
+                None
+            }
+            Some(b'P') => {
+                let mut index = sequence_start + 2;
+                while matches!(self.byte_at(index), Some(0x30..=0x3f)) {
+                    index += 1; // parameters
+                }
+                let mut has_intermediates = false;
+                while matches!(self.byte_at(index), Some(0x20..=0x2f)) {
+                    has_intermediates = true;
+                    index += 1;
+                }
+                let control_string = if self.byte_at(index) == Some(b'q') && !has_intermediates {
+                    ControlString::Sixel
+                } else {
+                    ControlString::Other
+                };
+                self.position = index;
+                self.skip_control_string(sequence_start)
+                    .map(|range| (range, control_string))
+            }
+            Some(b'X' | b'^' | b'_') => {
+                // SOS, PM and APC
+                self.position = sequence_start + 2;
+                self.skip_control_string(sequence_start)
+                    .map(|range| (range, ControlString::Other))
+            }
+            Some(_) => {
+                // other escape sequences: intermediate bytes followed by a single final byte
+                let mut index = sequence_start + 1;
+                while matches!(self.byte_at(index), Some(0x20..=0x2f)) {
+                    index += 1;
+                }
+                self.position = std::cmp::min(index + 1, self.bytes.len());
+                None
+            }
+            None => {
+                self.position = self.bytes.len();
+                None
+            }
+        }
+    }
+    /// Moves past the end of the control string whose body starts at `self.position`, returning
+    /// its full range if it was properly terminated by ST
+    fn skip_control_string(&mut self, sequence_start: usize) -> Option<Range<usize>> {
+        while let Some(byte) = self.byte_at(self.position) {
+            match byte {
+                ESC => {
+                    if self.byte_at(self.position + 1) == Some(b'\\') {
+                        self.position += 2;
+                        return Some(sequence_start..self.position);
+                    }
+                    // an ESC that does not terminate the string aborts it and starts a new
+                    // sequence, so we leave it to be scanned again
+                    return None;
+                }
+                CAN | SUB => {
+                    self.position += 1;
+                    return None;
+                }
+                _ => self.position += 1,
+            }
+        }
+        None
+    }
+}
+
+impl Iterator for SixelScanner<'_> {
+    type Item = EmbeddedSixelImage;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.bytes.len() {
+            let next_escape = self.bytes[self.position..]
+                .iter()
+                .position(|byte| *byte == ESC);
+            match next_escape {
+                Some(offset) => self.position += offset,
+                None => {
+                    self.position = self.bytes.len();
+                    break;
+                }
+            }
+            if let Some((range, ControlString::Sixel)) = self.skip_escape_sequence() {
+                if let Ok(image) = SixelImage::new(&self.bytes[range.clone()]) {
+                    return Some(EmbeddedSixelImage { image, range });
+                }
+            }
+        }
+        None
+    }
+}
@@ -1,12 +1,19 @@
 use std::collections::{BTreeMap, HashMap};
 
-use crate::{Pixel, SixelColor, DCS, RA};
+use crate::{resolve_register_color, Pixel, Rect, SixelBandCache, SixelColor, DCS, RA};
+
+// runs of identical sixel characters at least this long are written with a repeat introducer
+// (`!<count><character>`), shorter ones character by character
+const REPEAT_THRESHOLD: usize = 3;
 
 pub struct SixelSerializer<'a> {
     dcs: &'a DCS,
     ra: &'a Option<RA>,
     color_registers: &'a BTreeMap<u16, SixelColor>,
     pixels: &'a Vec<Vec<Pixel>>,
+    mask: &'a [Rect],
+    monochrome: bool,
+    canonical: bool,
 }
 
 impl<'a> SixelSerializer<'a> {
@@ -21,16 +28,48 @@ impl<'a> SixelSerializer<'a> {
             ra,
             color_registers,
             pixels,
+            mask: &[],
+            monochrome: false,
+            canonical: false,
         }
     }
+    /// Provide a list of rectangles (in pixels) to exclude from serialization, all pixels inside
+    /// them will be serialized as if they were off
+    pub fn mask(mut self, mask: &'a [Rect]) -> Self {
+        self.mask = mask;
+        self
+    }
+    /// Serialize all pixels that are on as one color without any color introducers or color
+    /// registers, so that they are drawn with the terminal's (or printer's) foreground color as
+    /// monochrome sixel devices do
+    pub fn monochrome(mut self, monochrome: bool) -> Self {
+        self.monochrome = monochrome;
+        self
+    }
+    /// Serialize the image in a canonical form, so that the same picture always produces the same
+    /// bytes no matter how it was encoded: color registers are renumbered from 0 in the order
+    /// they are first drawn with, registers with the same color are merged, unused ones are
+    /// dropped, and all colors (including the defaults of undefined registers) are defined
+    /// explicitly as RGB
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
     pub fn serialize(&self) -> String {
+        if self.canonical {
+            let (height, width) = (self.pixels.len(), self.pixels.iter().map(Vec::len).max());
+            let (color_registers, pixels) =
+                self.canonical_registers_and_pixels(0, 0, width.unwrap_or(0), height);
+            return self
+                .with_registers_and_pixels(&color_registers, &pixels)
+                .serialize();
+        }
         let serialized_image = String::new();
         let serialized_image = self.serialize_dcs(serialized_image);
         let serialized_image = self.serialize_ra(serialized_image);
         let serialized_image = self.serialize_color_registers(serialized_image);
         let serialized_image = self.serialize_pixels(serialized_image, None, None, None, None);
-        let serialized_image = self.serialize_end_event(serialized_image);
-        serialized_image
+        self.serialize_end_event(serialized_image)
     }
     pub fn serialize_range(
         &self,
@@ -39,6 +78,13 @@ impl<'a> SixelSerializer<'a> {
         width: usize,
         height: usize,
     ) -> String {
+        if self.canonical {
+            let (color_registers, pixels) =
+                self.canonical_registers_and_pixels(start_x_index, start_y_index, width, height);
+            return self
+                .with_registers_and_pixels(&color_registers, &pixels)
+                .serialize_range(start_x_index, start_y_index, width, height);
+        }
         let serialized_image = String::new();
         let serialized_image = self.serialize_dcs(serialized_image);
         let serialized_image = self.serialize_ra(serialized_image);
@@ -50,8 +96,113 @@ impl<'a> SixelSerializer<'a> {
             Some(width),
             Some(height),
         );
-        let serialized_image = self.serialize_end_event(serialized_image);
-        serialized_image
+        self.serialize_end_event(serialized_image)
+    }
+    pub(crate) fn serialize_cached_range(
+        &self,
+        sixel_band_cache: &SixelBandCache,
+        start_x_index: usize,
+        start_y_index: usize,
+        width: usize,
+        height: usize,
+    ) -> String {
+        let serialized_image = String::new();
+        let serialized_image = self.serialize_dcs(serialized_image);
+        let serialized_image = self.serialize_ra(serialized_image);
+        let serialized_image = self.serialize_color_registers(serialized_image);
+        let serialized_image = self.serialize_cached_pixels(
+            serialized_image,
+            sixel_band_cache,
+            start_x_index,
+            start_y_index,
+            width,
+            height,
+        );
+        self.serialize_end_event(serialized_image)
+    }
+    // a non-canonical copy of this serializer for the given registers and pixels
+    fn with_registers_and_pixels<'b>(
+        &'b self,
+        color_registers: &'b BTreeMap<u16, SixelColor>,
+        pixels: &'b Vec<Vec<Pixel>>,
+    ) -> SixelSerializer<'b> {
+        SixelSerializer::new(self.dcs, self.ra, color_registers, pixels)
+            .mask(self.mask)
+            .monochrome(self.monochrome)
+    }
+    // Renumbers the registers of the pixels in the range in the order the serializer will reach
+    // them (band by band, column by column, top to bottom within a column), giving registers
+    // with the same color the same number. Pixels that will not be serialized are turned off, and
+    // trailing pixels that are off are dropped
+    fn canonical_registers_and_pixels(
+        &self,
+        start_x_index: usize,
+        start_y_index: usize,
+        width: usize,
+        height: usize,
+    ) -> (BTreeMap<u16, SixelColor>, Vec<Vec<Pixel>>) {
+        let mut color_registers = BTreeMap::new();
+        let mut pixels: Vec<Vec<Pixel>> = self
+            .pixels
+            .iter()
+            .map(|row| {
+                vec![
+                    Pixel {
+                        on: false,
+                        color: 0
+                    };
+                    row.len()
+                ]
+            })
+            .collect();
+        let mut canonical_registers: HashMap<u16, u16> = HashMap::new();
+        let mut registers_by_color: HashMap<(u8, u8, u8), u16> = HashMap::new();
+        let end_y_index = std::cmp::min(start_y_index.saturating_add(height), self.pixels.len());
+        for band_start in (start_y_index..end_y_index).step_by(6) {
+            let band_end = std::cmp::min(band_start + 6, end_y_index);
+            let band_width = self.pixels[band_start..band_end]
+                .iter()
+                .map(Vec::len)
+                .max()
+                .unwrap_or(0);
+            let end_x_index = std::cmp::min(start_x_index.saturating_add(width), band_width);
+            for x in start_x_index..end_x_index {
+                for y in band_start..band_end {
+                    let pixel = match self.pixels.get(y).and_then(|row| row.get(x)) {
+                        Some(pixel) if pixel.on => pixel,
+                        _ => continue,
+                    };
+                    if self.mask.iter().any(|rect| rect.contains(x, y)) {
+                        continue;
+                    }
+                    let color = *canonical_registers.entry(pixel.color).or_insert_with(|| {
+                        let sixel_color = resolve_register_color(self.color_registers, pixel.color);
+                        *registers_by_color
+                            .entry(sixel_color.to_rgb8())
+                            .or_insert_with(|| {
+                                let color_register = color_registers.len() as u16;
+                                color_registers
+                                    .insert(color_register, sixel_color.to_rgb_percent());
+                                color_register
+                            })
+                    });
+                    if let Some(pixel) = pixels.get_mut(y).and_then(|row| row.get_mut(x)) {
+                        *pixel = Pixel { on: true, color };
+                    }
+                }
+            }
+        }
+        // trailing pixels that are off make no difference to the picture, except for the width
+        // of the first row which is the width of the image
+        for (y, row) in pixels.iter_mut().enumerate() {
+            let mut row_length = row.iter().rposition(|pixel| pixel.on).map_or(0, |x| x + 1);
+            if y == start_y_index {
+                let range_end = start_x_index.saturating_add(width);
+                row_length = std::cmp::max(row_length, std::cmp::min(row.len(), range_end));
+            }
+            row.truncate(row_length);
+        }
+        (color_registers, pixels)
     }
     fn serialize_dcs(&self, mut append_to: String) -> String {
         append_to.push_str(&format!(
@@ -78,7 +229,10 @@ impl<'a> SixelSerializer<'a> {
         append_to
     }
     fn serialize_color_registers(&self, mut append_to: String) -> String {
-        for (color_register, sixel_color_code) in &*self.color_registers {
+        if self.monochrome {
+            return append_to;
+        }
+        for (color_register, sixel_color_code) in self.color_registers {
             match sixel_color_code {
                 SixelColor::Hsl(x, y, z) => {
                     append_to.push_str(&format!("#{};1;{};{};{}", color_register, x, y, z))
@@ -114,7 +268,9 @@ impl<'a> SixelSerializer<'a> {
                 current_column_index,
                 max_x_index,
                 max_y_index,
-                &self.pixels,
+                self.pixels,
+                self.mask,
+                self.monochrome,
             )
             .map(|mut sixel_column| {
                 sixel_column
@@ -128,6 +284,7 @@ impl<'a> SixelSerializer<'a> {
                     relative_line_index,
                     relative_column_index,
                     max_lines,
+                    !self.monochrome,
                 )
                 .as_mut()
                 .map(|sixel_line| {
@@ -143,6 +300,39 @@ impl<'a> SixelSerializer<'a> {
         }
         append_to
     }
+    fn serialize_cached_pixels(
+        &self,
+        mut append_to: String,
+        sixel_band_cache: &SixelBandCache,
+        start_x_index: usize,
+        start_y_index: usize,
+        width: usize,
+        height: usize,
+    ) -> String {
+        let max_y_index = (start_y_index + height).saturating_sub(1);
+        let max_lines = sixel_band_cache.max_lines(height);
+        let mut relative_line_index = 0;
+        while relative_line_index < max_lines {
+            let (mut color_index_to_sixel_data_string, line_length) = sixel_band_cache
+                .band_characters(
+                    start_y_index + relative_line_index,
+                    start_x_index,
+                    width,
+                    max_y_index,
+                );
+            if let Some(mut sixel_line) = SixelLine::new(
+                &mut append_to,
+                relative_line_index,
+                line_length,
+                max_lines,
+                true,
+            ) {
+                sixel_line.serialize(&mut color_index_to_sixel_data_string);
+            }
+            relative_line_index += 6;
+        }
+        append_to
+    }
     fn serialize_end_event(&self, mut append_to: String) -> String {
         append_to.push_str("\u{1b}\\");
         append_to
@@ -159,7 +349,9 @@ impl SixelColumn {
         absolute_column_index: usize,
         max_x_index: Option<usize>,
         max_y_index: Option<usize>,
-        pixels: &Vec<Vec<Pixel>>,
+        pixels: &[Vec<Pixel>],
+        mask: &[Rect],
+        monochrome: bool,
     ) -> Option<Self> {
         let mut empty_rows = 0;
         let mut color_index_to_byte = HashMap::new();
@@ -184,8 +376,12 @@ impl SixelColumn {
                 .map(|current_line| current_line.get(absolute_column_index));
             match pixel_at_current_position {
                 Some(Some(pixel)) => {
-                    if pixel.on {
-                        let color_char = color_index_to_byte.entry(pixel.color).or_insert(0);
+                    let is_masked = mask
+                        .iter()
+                        .any(|rect| rect.contains(absolute_column_index, absolute_line_index + i));
+                    if pixel.on && !is_masked {
+                        let color_index = if monochrome { 0 } else { pixel.color };
+                        let color_char = color_index_to_byte.entry(color_index).or_insert(0);
                         let mask = 1 << i;
                         *color_char += mask;
                     }
@@ -210,7 +406,7 @@ impl SixelColumn {
         for (color_index, char_representation) in self.color_index_to_byte.iter_mut() {
             let color_chars = color_index_to_character_string
                 .entry(*color_index)
-                .or_insert(String::new());
+                .or_default();
             for _ in color_chars.len()..current_index {
                 color_chars.push('?');
             }
@@ -223,6 +419,7 @@ struct SixelLine<'a> {
     append_to: &'a mut String,
     relative_line_index: usize, // line index inside cropped selection, or as part of total if not cropping
     line_length: usize,
+    with_color_introducers: bool,
 }
 
 impl<'a> SixelLine<'a> {
@@ -231,6 +428,7 @@ impl<'a> SixelLine<'a> {
         relative_line_index: usize,
         relative_column_index: usize,
         max_lines: usize,
+        with_color_introducers: bool,
     ) -> Option<Self> {
         if relative_line_index >= max_lines {
             None
@@ -239,6 +437,7 @@ impl<'a> SixelLine<'a> {
                 append_to,
                 relative_line_index,
                 line_length: relative_column_index,
+                with_color_introducers,
             })
         }
     }
@@ -253,7 +452,9 @@ impl<'a> SixelLine<'a> {
             }
             is_first = false;
             self.pad_sixel_string(sixel_chars, self.line_length);
-            self.serialize_color_introducer(color_index);
+            if self.with_color_introducers {
+                self.serialize_color_introducer(color_index);
+            }
             self.group_identical_characters(sixel_chars);
         }
         color_index_to_character_string.clear();
@@ -263,7 +464,7 @@ impl<'a> SixelLine<'a> {
         character_occurrences: usize,
         character: char,
     ) {
-        if character_occurrences > 2 {
+        if character_occurrences >= REPEAT_THRESHOLD {
             self.append_to
                 .push_str(&format!("!{}{}", character_occurrences, character));
         } else {
@@ -1,4 +1,9 @@
-use crate::SixelImage;
+use crate::{
+    EmbeddedSixelImage, Rect, RgbaImportOptions, SixelAnimation, SixelColor, SixelDeserializer,
+    SixelImage, SixelScanner, TextRenderMode,
+};
+use sixel_tokenizer::Parser;
+use std::time::Duration;
 
 fn remove_whitespace(s: &str) -> String {
     let mut s = s.to_string();
@@ -26,7 +31,7 @@ fn basic_serialization() {
     ";
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize();
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -65,12 +70,12 @@ fn full_256_colors() {
     for i in 0..256 {
         sample.push_str(&format!("#{};1;50;50;50", i));
     }
-    sample.push_str(&"\u{1b}\\");
+    sample.push_str("\u{1b}\\");
     let mut expected = String::from("\u{1b}P0;0;0q");
     for i in 0..256 {
         expected.push_str(&format!("#{};1;50;50;50", i));
     }
-    expected.push_str(&"\u{1b}\\");
+    expected.push_str("\u{1b}\\");
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize();
     assert_eq!(serialized_image, expected);
@@ -96,7 +101,7 @@ fn color_definition_at_the_end() {
     ";
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize();
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -117,7 +122,7 @@ fn multiple_occurrences_into_repeat_characters() {
     ";
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize();
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -146,7 +151,7 @@ fn color_introducer_event() {
     ";
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize();
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -193,7 +198,7 @@ fn crop_image_width_and_height() {
     "; // only the upper left pixel
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize_range(0, 0, 1, 1); // x, y, width, height
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -214,7 +219,7 @@ fn crop_image_width_and_height_mid_image() {
     "; // 5x5 pixels starting from x==5 y==5
     let sixel_image = SixelImage::new(sample.as_bytes());
     let serialized_image = sixel_image.unwrap().serialize_range(5, 5, 5, 5); // x, y, width, height
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -238,7 +243,7 @@ fn cut_out_from_image() {
     let mut sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
     sixel_image.cut_out(1, 1, 5, 5); // cut out a rect starting from x/y 1/1 with a width and height of 5 and 5 respectively
     let serialized_image = sixel_image.serialize();
-    assert_eq!(serialized_image, remove_whitespace(&expected));
+    assert_eq!(serialized_image, remove_whitespace(expected));
 }
 
 #[test]
@@ -271,3 +276,1009 @@ fn serialize_range_adjusts_raster_attributes() {
         serialized
     );
 }
+
+#[test]
+fn drop_top_rows_realigns_sixel_bands() {
+    let sample = "
+        \u{1b}Pq
+        \"1;1;14;12
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #1!14@
+        \u{1b}\\
+    ";
+    let expected = "
+        \u{1b}P0;0;0q
+        \"1;1;14;9
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #0!14o$
+        #1NNGGMMGGNNGGNN$
+        #2??FF@@FF??FF??-
+        #0!14F
+        \u{1b}\\
+    ";
+    let mut sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    sixel_image.drop_top_rows(3);
+    assert_eq!(sixel_image.pixel_size(), (9, 14));
+    assert_eq!(sixel_image.serialize(), remove_whitespace(expected));
+}
+
+#[test]
+fn band_cache_serializes_ranges_like_the_image() {
+    let sample = "
+        \u{1b}Pq
+        \"1;1;16;15
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #1!14@$
+        #2??NN??oo-
+        #1!3~!5?wwF
+        \u{1b}\\
+    ";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let band_cache = sixel_image.band_cache();
+    assert_eq!(band_cache.pixel_size(), sixel_image.pixel_size());
+    for y in 0..15 {
+        for x in 0..16 {
+            for (width, height) in [(1, 1), (3, 7), (5, 5), (16, 15), (8, 12), (20, 20)] {
+                assert_eq!(
+                    band_cache.serialize_range(x, y, width, height),
+                    sixel_image.serialize_range(x, y, width, height),
+                    "x: {}, y: {}, width: {}, height: {}",
+                    x,
+                    y,
+                    width,
+                    height
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn serialize_with_multiple_mask_rectangles() {
+    let sample = "
+        \u{1b}Pq
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #1!14@
+        \u{1b}\\
+    ";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let mask = [Rect::new(1, 1, 5, 5), Rect::new(10, 6, 2, 1)];
+    let mut cut_out_image = sixel_image.clone();
+    for rect in &mask {
+        cut_out_image.cut_out(rect.x, rect.y, rect.width, rect.height);
+    }
+    let expected = "
+        \u{1b}P0;0;0q
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~!7@~~@@~~$
+        #2!6?}}??}}??-
+        #1!10@??@@
+        \u{1b}\\
+    ";
+    assert_eq!(
+        sixel_image.serialize_with_mask(&mask),
+        remove_whitespace(expected)
+    );
+    assert_eq!(
+        sixel_image.serialize_with_mask(&mask),
+        cut_out_image.serialize()
+    );
+    assert_eq!(
+        sixel_image.serialize_with_mask(&[]),
+        sixel_image.serialize()
+    );
+    // rectangles reaching beyond the largest coordinates mask everything to their bottom right
+    let mut unbounded_cut_out = sixel_image.clone();
+    unbounded_cut_out.cut_out(1, 0, usize::MAX, 1);
+    assert_eq!(
+        sixel_image.serialize_with_mask(&[Rect::new(1, 0, usize::MAX, 1)]),
+        unbounded_cut_out.serialize()
+    );
+    assert!(Rect::new(1, 1, usize::MAX, usize::MAX).contains(usize::MAX - 1, 5));
+}
+
+#[test]
+fn diff_images_of_the_same_size() {
+    let sample = "
+        \u{1b}Pq
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #1!14@-
+        #2!14~
+        \u{1b}\\
+    ";
+    let renumbered_sample = "
+        \u{1b}Pq
+        #5;2;100;100;0#6;2;0;100;0
+        #5~~@@vv@@~~@@~~$
+        #6??}}GG}}??}}??-
+        #5!14@-
+        #6!14~
+        \u{1b}\\
+    ";
+    let changed_sample = "
+        \u{1b}Pq
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}?A}}??-
+        #1!3@!11?$
+        #2???~-
+        #2!3~!11N
+        \u{1b}\\
+    ";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let renumbered_image = SixelImage::new(renumbered_sample.as_bytes()).unwrap();
+    let changed_image = SixelImage::new(changed_sample.as_bytes()).unwrap();
+    assert_eq!(sixel_image.diff(&renumbered_image), Ok(vec![]));
+    assert_eq!(
+        changed_image.diff(&sixel_image),
+        Ok(vec![Rect::new(9, 0, 1, 6), Rect::new(3, 6, 11, 12)])
+    );
+    let serialized_diff = changed_image.serialize_diff(&sixel_image).unwrap();
+    assert_eq!(
+        serialized_diff,
+        vec![
+            (
+                Rect::new(9, 0, 1, 6),
+                changed_image.serialize_range(9, 0, 1, 6)
+            ),
+            (
+                Rect::new(3, 6, 11, 12),
+                changed_image.serialize_range(3, 6, 11, 12)
+            ),
+        ]
+    );
+    let mut smaller_image = sixel_image.clone();
+    smaller_image.drop_top_rows(1);
+    assert!(sixel_image.diff(&smaller_image).is_err());
+    // pixels are compared by the colors they resolve to, not by how their registers are defined
+    let rgb = SixelImage::new("\u{1b}P0;1q#1;2;100;0;0#1~~$#3~\u{1b}\\".as_bytes()).unwrap();
+    let hls = SixelImage::new("\u{1b}P0;1q#1;1;120;50;100#3;2;20;80;20#1~~$#3~\u{1b}\\".as_bytes())
+        .unwrap();
+    assert!(rgb == hls);
+    assert_eq!(rgb.diff(&hls).unwrap(), vec![]);
+}
+
+#[test]
+fn accumulate_animation_frames() {
+    let first_frame = "
+        \u{1b}Pq
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #1!14@
+        \u{1b}\\
+    ";
+    let second_frame = "
+        \u{1b}Pq
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #2!14@
+        \u{1b}\\
+    ";
+    let mut sixel_animation = SixelAnimation::new();
+    let (first_half, second_half) = second_frame.as_bytes().split_at(40);
+    sixel_animation
+        .advance(first_frame.as_bytes(), Duration::from_millis(0))
+        .unwrap();
+    sixel_animation
+        .advance(first_half, Duration::from_millis(40))
+        .unwrap();
+    assert_eq!(sixel_animation.frame_count(), 1);
+    sixel_animation
+        .advance(second_half, Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(sixel_animation.frame_count(), 2);
+    assert_eq!(
+        sixel_animation.frame_duration(0),
+        Some(Duration::from_millis(50))
+    );
+    assert_eq!(sixel_animation.frame_duration(1), None);
+    assert_eq!(sixel_animation.total_duration(), Duration::from_millis(50));
+    assert_eq!(
+        sixel_animation.serialize_frame(1),
+        Some(
+            SixelImage::new(second_frame.as_bytes())
+                .unwrap()
+                .serialize()
+        )
+    );
+    let expected_delta = "
+        \u{1b}P0;0;0q
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #2!14@
+        \u{1b}\\
+    ";
+    assert_eq!(
+        sixel_animation.serialize_delta(0, 1),
+        Ok(vec![(
+            Rect::new(0, 6, 14, 6),
+            remove_whitespace(expected_delta)
+        )])
+    );
+    assert!(sixel_animation.serialize_delta(0, 2).is_err());
+}
+
+#[test]
+fn scan_sixel_images_embedded_in_terminal_output() {
+    let first_image = "\u{1b}Pq#0;2;0;0;0#1;2;100;100;0#1~~@@vv@@~~@@~~-#1!14@\u{1b}\\";
+    let second_image = "\u{1b}P0;1;0q\"1;1;2;6#2;2;0;100;0#2~~\u{1b}\\";
+    let output = format!(
+        "$ cat image.six\r\n\u{1b}[1;32mok\u{1b}[0m\u{1b}]0;title\u{7}{}\u{1b}P$qm\u{1b}\\\u{1b}]8;;https://example.com\u{1b}\\link\u{1b}Pq#0~\u{1b}[A\u{1b}_Gf=32;AAAA\u{1b}\\{}\r\n",
+        first_image, second_image
+    );
+    let embedded_images: Vec<EmbeddedSixelImage> = SixelScanner::new(output.as_bytes()).collect();
+    assert_eq!(embedded_images.len(), 2);
+    let first_start = output.find(first_image).unwrap();
+    let second_start = output.find(second_image).unwrap();
+    assert_eq!(
+        embedded_images[0].range,
+        first_start..first_start + first_image.len()
+    );
+    assert_eq!(
+        embedded_images[1].range,
+        second_start..second_start + second_image.len()
+    );
+    assert_eq!(
+        embedded_images[0].image.serialize(),
+        SixelImage::new(first_image.as_bytes()).unwrap().serialize()
+    );
+    assert_eq!(embedded_images[1].image.pixel_size(), (6, 2));
+}
+
+#[test]
+fn parse_multiple_consecutive_images() {
+    let first_image = "\u{1b}Pq#0;2;0;0;0#1;2;100;100;0#1~~@@vv@@~~@@~~-#1!14@\u{1b}\\";
+    let second_image = "\u{1b}P0;1;0q#2;2;0;100;0#2~~\u{1b}\\";
+    let third_image = "\u{1b}Pq#3;2;100;0;0#3!5N";
+    let bytes = format!("{}\n{}{}", first_image, second_image, third_image);
+    let sixel_images = SixelImage::parse_all(bytes.as_bytes()).unwrap();
+    assert_eq!(sixel_images.len(), 3);
+    assert_eq!(
+        sixel_images[0].serialize(),
+        SixelImage::new(first_image.as_bytes()).unwrap().serialize()
+    );
+    assert_eq!(
+        sixel_images[1].serialize(),
+        SixelImage::new(second_image.as_bytes())
+            .unwrap()
+            .serialize()
+    );
+    assert_eq!(sixel_images[2].pixel_size(), (6, 5));
+    assert!(SixelImage::parse_all(b"").is_err());
+}
+
+#[test]
+fn deserializer_resets_after_creating_an_image() {
+    let bytes = "\u{1b}Pq#1;2;100;100;0#1~~\u{1b}\\\u{1b}Pq#2;2;0;100;0#2N\u{1b}\\";
+    let mut parser = Parser::new();
+    let mut sixel_deserializer = SixelDeserializer::new();
+    let mut sixel_images = vec![];
+    for byte in bytes.as_bytes() {
+        parser.advance(byte, |sixel_event| {
+            sixel_deserializer.handle_event(sixel_event).unwrap();
+        });
+        if sixel_deserializer.is_complete() {
+            sixel_images.push(sixel_deserializer.create_image().unwrap());
+        }
+    }
+    assert!(!sixel_deserializer.is_complete());
+    assert!(sixel_deserializer.create_image().is_err());
+    assert_eq!(sixel_images.len(), 2);
+    assert_eq!(
+        sixel_images[1].serialize(),
+        "\u{1b}P0;0;0q#2;2;0;100;0#2N\u{1b}\\"
+    );
+}
+
+#[test]
+fn deserializer_completes_images_cut_short_by_max_height() {
+    let bytes = "\u{1b}Pq#1;2;100;100;0#1~~-~~-~~\u{1b}\\\u{1b}Pq#2;2;0;100;0#2N-N\u{1b}\\";
+    let mut parser = Parser::new();
+    let mut sixel_deserializer = SixelDeserializer::new().max_height(6);
+    let mut sixel_images = vec![];
+    for byte in bytes.as_bytes() {
+        parser.advance(byte, |sixel_event| {
+            sixel_deserializer.handle_event(sixel_event).unwrap();
+        });
+        if sixel_deserializer.is_complete() {
+            sixel_images.push(sixel_deserializer.create_image().unwrap());
+        }
+    }
+    assert_eq!(sixel_images.len(), 2);
+    assert_eq!(sixel_images[0].pixel_size(), (6, 2));
+    assert_eq!(
+        sixel_images[1].serialize(),
+        "\u{1b}P0;0;0q#2;2;0;100;0#2N\u{1b}\\"
+    );
+}
+
+#[test]
+fn render_text_fallback() {
+    let sample = "\u{1b}Pq#1;2;100;100;100#2;2;0;0;100#1NB@$#2?K?\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    assert_eq!(
+        sixel_image.render_text(3, 3, TextRenderMode::HalfBlocksTrueColor),
+        vec![
+            "\u{1b}[38;2;255;255;255m██▀\u{1b}[0m",
+            "\u{1b}[38;2;255;255;255m█\u{1b}[38;2;0;0;255m█\u{1b}[0m ",
+            "   ",
+        ]
+    );
+    assert_eq!(
+        sixel_image.render_text(3, 2, TextRenderMode::HalfBlocks256),
+        vec![
+            "\u{1b}[38;5;231m██▀\u{1b}[0m",
+            "\u{1b}[38;5;231m▀\u{1b}[38;5;21m▀\u{1b}[0m ",
+        ]
+    );
+    assert_eq!(
+        sixel_image.render_text(2, 1, TextRenderMode::BrailleThreshold(128)),
+        vec!["⠿⠋"]
+    );
+    assert_eq!(
+        sixel_image.render_text(2, 1, TextRenderMode::Braille),
+        vec!["⠿⠏"]
+    );
+    // monochrome images draw with the (black) default color of register 0
+    let monochrome = SixelImage::new("\u{1b}P0;1q~~\u{1b}\\".as_bytes()).unwrap();
+    assert_eq!(
+        monochrome.render_text(1, 2, TextRenderMode::Braille),
+        vec!["⣿", "⣿"]
+    );
+}
+
+#[test]
+fn convert_sixel_colors_to_rgb() {
+    assert_eq!(SixelColor::Rgb(100, 50, 0).to_rgb8(), (255, 128, 0));
+    assert_eq!(SixelColor::Hsl(0, 50, 100).to_rgb8(), (0, 0, 255)); // hue 0 is blue in sixel
+    assert_eq!(SixelColor::Hsl(120, 50, 100).to_rgb8(), (255, 0, 0));
+    assert_eq!(SixelColor::Hsl(240, 50, 100).to_rgb8(), (0, 255, 0));
+    assert_eq!(SixelColor::Hsl(0, 100, 0).to_rgb8(), (255, 255, 255));
+}
+
+#[test]
+fn convert_to_and_from_rgba() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1N$#2?K\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let rgba = sixel_image.to_rgba();
+    #[rustfmt::skip]
+    let expected = vec![
+        255, 0, 0, 255, 0, 0, 0, 0,
+        255, 0, 0, 255, 0, 0, 0, 0,
+        255, 0, 0, 255, 0, 0, 255, 255,
+        255, 0, 0, 255, 0, 0, 255, 255,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    assert_eq!(rgba, expected);
+    let from_rgba = SixelImage::from_rgba(2, 6, &rgba, &Default::default()).unwrap();
+    assert_eq!(
+        from_rgba.serialize(),
+        "\u{1b}P0;1;0q\"1;1;2;6#0;2;100;0;0#1;2;0;0;100#0N?$#1?K\u{1b}\\"
+    );
+    assert!(SixelImage::from_rgba(3, 6, &rgba, &Default::default()).is_err());
+}
+
+#[test]
+fn quantize_rgba_to_256_color_registers() {
+    let (width, height) = (64, 64);
+    let mut rgba = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            rgba.extend_from_slice(&[(x * 4) as u8, (y * 4) as u8, 128, 255]);
+        }
+    }
+    let sixel_image = SixelImage::from_rgba(width, height, &rgba, &Default::default()).unwrap();
+    assert_eq!(sixel_image.color_registers.len(), 256);
+    assert_eq!(sixel_image.pixel_size(), (64, 64));
+    for (original, quantized) in rgba.iter().zip(sixel_image.to_rgba().iter()) {
+        assert!((*original as i32 - *quantized as i32).abs() <= 16);
+    }
+}
+
+#[test]
+fn convert_to_and_from_kitty_graphics_commands() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1N$#2?K\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let expected = "\u{1b}_Ga=T,f=32,s=2,v=6,q=2,m=0;/wAA/wAAAAD/AAD/AAAAAP8AAP8AAP///wAA/wAA//8AAAAAAAAAAAAAAAAAAAAA\u{1b}\\";
+    assert_eq!(sixel_image.serialize_kitty(), expected);
+    let from_kitty = SixelImage::from_kitty(expected.as_bytes(), &Default::default()).unwrap();
+    assert_eq!(from_kitty.to_rgba(), sixel_image.to_rgba());
+
+    let large_sample = "\u{1b}Pq\"1;1;40;40#1;2;0;100;0#1!40~\u{1b}\\";
+    let large_image = SixelImage::new(large_sample.as_bytes()).unwrap();
+    let serialized = large_image.serialize_kitty();
+    assert_eq!(serialized.matches("\u{1b}_G").count(), 3);
+    assert!(serialized.starts_with("\u{1b}_Ga=T,f=32,s=40,v=40,q=2,m=1;"));
+    assert!(serialized.contains("\u{1b}\\\u{1b}_Gm=0;"));
+    let from_kitty = SixelImage::from_kitty(serialized.as_bytes(), &Default::default()).unwrap();
+    assert_eq!(from_kitty.to_rgba(), large_image.to_rgba());
+
+    let rgb_command = "\u{1b}_Gf=24,s=1,v=1;AP8A\u{1b}\\";
+    let from_rgb = SixelImage::from_kitty(rgb_command.as_bytes(), &Default::default()).unwrap();
+    assert_eq!(from_rgb.to_rgba(), vec![0, 255, 0, 255]);
+    assert!(
+        SixelImage::from_kitty(b"\x1b_Gf=32,s=1,v=1,o=z;AAAA\x1b\\", &Default::default()).is_err()
+    );
+    assert!(SixelImage::from_kitty(b"no graphics here", &Default::default()).is_err());
+    // sizes that overflow are rejected rather than panicking
+    assert!(SixelImage::from_kitty(
+        b"\x1b_Gf=32,s=4611686018427387904,v=4;\x1b\\",
+        &Default::default()
+    )
+    .is_err());
+    assert!(SixelImage::from_kitty(
+        b"\x1b_Gf=24,s=6148914691236517206,v=4;\x1b\\",
+        &Default::default()
+    )
+    .is_err());
+    assert!(SixelImage::from_rgba(usize::MAX, 2, &[], &Default::default()).is_err());
+}
+
+#[test]
+fn serialize_iterm2_inline_image() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#1@\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let expected_png_base64 = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAAGCAYAAAACEPQxAAAADklEQVR4AWP4zwBCOAAANucB/9Mwi/sAAAAASUVORK5CYII=";
+    assert_eq!(
+        sixel_image.serialize_iterm2(None, None),
+        format!(
+            "\u{1b}]1337;File=inline=1;size=71:{}\u{7}",
+            expected_png_base64
+        )
+    );
+    assert_eq!(
+        sixel_image.serialize_iterm2(Some(10), Some(2)),
+        format!(
+            "\u{1b}]1337;File=inline=1;size=71;width=10;height=2;preserveAspectRatio=0:{}\u{7}",
+            expected_png_base64
+        )
+    );
+    assert!(sixel_image
+        .serialize_iterm2(Some(4), None)
+        .starts_with("\u{1b}]1337;File=inline=1;size=71;width=4:"));
+
+    // the embedded PNG is compressed, rather than growing with the pixel count
+    let large_image =
+        SixelImage::new("\u{1b}P0;1q#1;2;100;0;0!600~-!600~-!600~\u{1b}\\".as_bytes()).unwrap();
+    assert!(large_image.serialize_iterm2(None, None).len() < 1000);
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn write_and_read_png() {
+    let sample = "
+        \u{1b}P0;1q
+        #0;2;0;0;0#1;2;100;100;0#2;2;0;100;0
+        #1~~@@vv@@~~@@~~$
+        #2??}}GG}}??}}??-
+        #1!14@
+        \u{1b}\\
+    ";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let mut png = vec![];
+    sixel_image.write_png(&mut png).unwrap();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    let from_png = SixelImage::from_png(png.as_slice(), &Default::default()).unwrap();
+    assert_eq!(from_png.pixel_size(), (12, 14));
+    assert_eq!(from_png.to_rgba(), sixel_image.to_rgba());
+    // the uncompressed PNGs used for iTerm2 inline images can be read as well
+    let iterm2_png = crate::png_writer::encode_rgba(14, 12, &sixel_image.to_rgba());
+    let from_iterm2_png = SixelImage::from_png(iterm2_png.as_slice(), &Default::default()).unwrap();
+    assert_eq!(from_iterm2_png.to_rgba(), sixel_image.to_rgba());
+    assert!(SixelImage::from_png(&b"not a png"[..], &Default::default()).is_err());
+}
+
+#[test]
+fn convert_to_and_from_netpbm_images() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1N$#2?K\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let mut expected_ppm = b"P6\n2 6\n255\n".to_vec();
+    for rgb in [
+        [255, 0, 0, 0, 0, 0],
+        [255, 0, 0, 0, 0, 0],
+        [255, 0, 0, 0, 0, 255],
+        [255, 0, 0, 0, 0, 255],
+        [0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0],
+    ] {
+        expected_ppm.extend_from_slice(&rgb);
+    }
+    assert_eq!(sixel_image.to_ppm(), expected_ppm);
+    let from_ppm = SixelImage::from_ppm(&expected_ppm).unwrap();
+    assert_eq!(from_ppm.rgb_at(0, 0), Some((255, 0, 0)));
+    assert_eq!(from_ppm.rgb_at(1, 0), Some((0, 0, 0)));
+
+    let pam = sixel_image.to_pam();
+    assert!(pam
+        .starts_with(b"P7\nWIDTH 2\nHEIGHT 6\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n"));
+    let from_pam = SixelImage::from_pam(&pam, &Default::default()).unwrap();
+    assert_eq!(from_pam.to_rgba(), sixel_image.to_rgba());
+
+    let commented_gray_pam =
+        b"P7\n# a comment\nWIDTH 2\nHEIGHT 1\nDEPTH 1\nMAXVAL 65535\nTUPLTYPE GRAYSCALE\nENDHDR\n\xff\xff\x00\x00";
+    let from_gray_pam = SixelImage::from_pam(commented_gray_pam, &Default::default()).unwrap();
+    assert_eq!(
+        from_gray_pam.to_rgba(),
+        vec![255, 255, 255, 255, 0, 0, 0, 255]
+    );
+    assert!(SixelImage::from_ppm(b"P6\n2 2\n255\n\x00\x00\x00").is_err());
+    assert!(SixelImage::from_pam(b"P6\n1 1\n255\n\x00\x00\x00", &Default::default()).is_err());
+    assert!(SixelImage::from_ppm(b"P6 0 4000000000 255\n").is_err());
+    assert!(SixelImage::from_pam(
+        b"P7\nWIDTH 4000000000\nHEIGHT 0\nDEPTH 3\nMAXVAL 255\nENDHDR\n",
+        &Default::default()
+    )
+    .is_err());
+}
+
+#[test]
+fn convert_to_and_from_bmp_images() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1N$#2?K\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let bmp = sixel_image.to_bmp();
+    assert_eq!(bmp.len(), 14 + 108 + 2 * 6 * 4);
+    assert_eq!(&bmp[..2], b"BM");
+    // rows are stored bottom-up as BGRA, so the last row holds the top left red pixel
+    assert_eq!(&bmp[bmp.len() - 8..], &[0, 0, 255, 255, 0, 0, 0, 0]);
+    let from_bmp = SixelImage::from_bmp(&bmp, &Default::default()).unwrap();
+    assert_eq!(from_bmp.to_rgba(), sixel_image.to_rgba());
+
+    // a 2x2 24-bit bitmap with a BITMAPINFOHEADER, each row padded to 8 bytes
+    let mut rgb_bmp = b"BM".to_vec();
+    rgb_bmp.extend_from_slice(&(54u32 + 16).to_le_bytes());
+    rgb_bmp.extend_from_slice(&[0, 0, 0, 0, 54, 0, 0, 0, 40, 0, 0, 0]);
+    rgb_bmp.extend_from_slice(&[2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0]);
+    rgb_bmp.extend_from_slice(&[0; 24]);
+    rgb_bmp.extend_from_slice(&[255, 0, 0, 0, 255, 0, 0, 0]); // bottom row: blue, green
+    rgb_bmp.extend_from_slice(&[0, 0, 255, 255, 255, 255, 0, 0]); // top row: red, white
+    let from_rgb_bmp = SixelImage::from_bmp(&rgb_bmp, &Default::default()).unwrap();
+    assert_eq!(from_rgb_bmp.rgb_at(0, 0), Some((255, 0, 0)));
+    assert_eq!(from_rgb_bmp.rgb_at(1, 0), Some((255, 255, 255)));
+    assert_eq!(from_rgb_bmp.rgb_at(0, 1), Some((0, 0, 255)));
+    assert_eq!(from_rgb_bmp.rgb_at(1, 1), Some((0, 255, 0)));
+    assert!(SixelImage::from_bmp(&rgb_bmp[..60], &Default::default()).is_err());
+    let mut empty_bmp = rgb_bmp.clone();
+    empty_bmp[18..26].copy_from_slice(&[0, 0, 0, 0, 255, 255, 255, 127]);
+    assert!(SixelImage::from_bmp(&empty_bmp, &Default::default()).is_err());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn convert_to_and_from_image_crate_types() {
+    use crate::{SixelDecoder, SixelEncoder};
+    use image::{DynamicImage, ExtendedColorType, ImageEncoder, RgbaImage};
+
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1N$#2?K\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let rgba_image = RgbaImage::from(&sixel_image);
+    assert_eq!(rgba_image.dimensions(), (2, 6));
+    assert_eq!(rgba_image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    assert_eq!(rgba_image.get_pixel(1, 0).0, [0, 0, 0, 0]);
+    let from_rgba_image = SixelImage::try_from(&rgba_image).unwrap();
+    assert_eq!(from_rgba_image.to_rgba(), sixel_image.to_rgba());
+    let dynamic_image = DynamicImage::from(&sixel_image);
+    let from_dynamic_image =
+        SixelImage::try_from(&DynamicImage::from(dynamic_image.to_rgb8())).unwrap();
+    assert_eq!(from_dynamic_image.rgb_at(1, 0), Some((0, 0, 0)));
+
+    let mut encoded = vec![];
+    SixelEncoder::new(&mut encoded)
+        .write_image(&[0, 255, 0, 255, 255, 255], 2, 1, ExtendedColorType::Rgb8)
+        .unwrap();
+    let encoded_image = SixelImage::new(&encoded).unwrap();
+    assert_eq!(encoded_image.rgb_at(0, 0), Some((0, 255, 0)));
+    assert_eq!(encoded_image.rgb_at(1, 0), Some((255, 255, 255)));
+    assert!(SixelEncoder::new(vec![])
+        .write_image(&[0; 8], 1, 1, ExtendedColorType::Rgba16)
+        .is_err());
+
+    let decoder = SixelDecoder::new(sample.as_bytes()).unwrap();
+    let decoded = DynamicImage::from_decoder(decoder).unwrap();
+    assert_eq!(decoded.to_rgba8(), rgba_image);
+}
+
+#[test]
+fn crop_and_scale_images() {
+    let sample = "\u{1b}P0;1q\"1;1;4;6#1;2;100;0;0#2;2;0;0;100#1NN$#2??KK\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    assert_eq!(sixel_image.dcs().macro_parameter(), 0);
+    assert!(sixel_image.dcs().transparent_bg());
+    assert_eq!(sixel_image.ra().and_then(|ra| ra.ph()), Some(4));
+
+    let mut cropped = sixel_image.clone();
+    cropped.crop(1, 1, 2, 3);
+    assert_eq!(cropped.pixel_size(), (3, 2));
+    assert_eq!(
+        cropped.serialize(),
+        "\u{1b}P0;1;0q\"1;1;2;3#1;2;100;0;0#2;2;0;0;100#1F?$#2?E\u{1b}\\"
+    );
+    let mut overflowing_crop = sixel_image.clone();
+    overflowing_crop.crop(3, 4, 10, 10);
+    assert_eq!(overflowing_crop.pixel_size(), (2, 1));
+
+    let mut scaled = sixel_image.clone();
+    scaled.scale(8, 12);
+    assert_eq!(scaled.pixel_size(), (12, 8));
+    assert_eq!(scaled.ra().and_then(|ra| ra.pv()), Some(12));
+    assert_eq!(scaled.rgb_at(1, 7), Some((255, 0, 0)));
+    assert_eq!(scaled.rgb_at(7, 7), Some((0, 0, 255)));
+    assert_eq!(scaled.rgb_at(7, 0), None);
+    scaled.scale(4, 6);
+    assert_eq!(scaled.to_rgba(), sixel_image.to_rgba());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_and_deserialize_with_serde() {
+    let sample = "\u{1b}P0;1q\"1;1;4;6#1;2;100;0;0#2;1;120;50;100#1NN$#2??KK\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let json = serde_json::to_string(&sixel_image).unwrap();
+    // pixel rows are stored as runs of (count, on, color)
+    assert!(json.contains(
+        "\"pixels\":[[[2,true,1],[2,false,2]],[[2,true,1],[2,false,2]],[[2,true,1],[2,true,2]],"
+    ));
+    let deserialized: SixelImage = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.serialize(), sixel_image.serialize());
+    assert_eq!(
+        serde_json::to_string(&SixelColor::Hsl(120, 50, 100)).unwrap(),
+        "{\"Hsl\":[120,50,100]}"
+    );
+    // run counts from untrusted input are not expanded without bounds
+    let huge_run = json.replace(
+        "[[2,true,1],[2,false,2]]",
+        "[[18446744073709551615,true,0]]",
+    );
+    assert!(serde_json::from_str::<SixelImage>(&huge_run).is_err());
+}
+
+#[test]
+fn image_statistics() {
+    let sample = "\u{1b}P0;1q\"1;1;4;6#1;2;100;0;0#2;1;120;50;100#3;2;0;0;0#1?NN$#2???K\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let stats = sixel_image.stats();
+    assert_eq!(stats.defined_registers, 3);
+    assert_eq!(stats.used_registers, 2);
+    assert_eq!(
+        stats.register_pixel_counts.into_iter().collect::<Vec<_>>(),
+        vec![(1, 8), (2, 2)]
+    );
+    assert_eq!(stats.off_pixels, 14);
+    assert_eq!(stats.painted_bounds, Some(Rect::new(1, 0, 3, 4)));
+    assert!(!stats.is_fully_opaque);
+
+    let opaque_sample = "\u{1b}Pq\"1;1;2;6#1;2;0;100;0#1~~\u{1b}\\";
+    let opaque_stats = SixelImage::new(opaque_sample.as_bytes()).unwrap().stats();
+    assert!(opaque_stats.is_fully_opaque);
+    assert_eq!(opaque_stats.painted_bounds, Some(Rect::new(0, 0, 2, 6)));
+}
+
+#[test]
+fn trim_to_the_painted_bounding_box() {
+    let sample = "\u{1b}P0;1q\"1;1;6;12#1;2;100;0;0#2;2;0;0;0#2!6@-#2~#1?K$#2?~??~~\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    assert_eq!(sixel_image.pixel_size(), (12, 6));
+    assert_eq!(sixel_image.bounding_box(None), Some(Rect::new(0, 0, 6, 12)));
+    assert_eq!(
+        sixel_image.bounding_box(Some(2)),
+        Some(Rect::new(2, 8, 1, 2))
+    );
+
+    let mut trimmed = sixel_image.clone();
+    assert_eq!(trimmed.trim(Some(2)), Some(Rect::new(2, 8, 1, 2)));
+    assert_eq!(trimmed.pixel_size(), (2, 1));
+    assert_eq!(trimmed.ra().and_then(|ra| ra.ph()), Some(1));
+    assert_eq!(trimmed.ra().and_then(|ra| ra.pv()), Some(2));
+    assert_eq!(trimmed.rgb_at(0, 0), Some((255, 0, 0)));
+
+    let mut empty = SixelImage::new("\u{1b}P0;1q\"1;1;4;6\u{1b}\\".as_bytes()).unwrap();
+    assert_eq!(empty.bounding_box(None), None);
+    assert_eq!(empty.trim(None), None);
+    assert_eq!(empty.pixel_size(), (0, 0));
+}
+
+#[test]
+fn draw_on_images() {
+    let sample = "\u{1b}P0;1q\"1;1;6;6#1;2;100;0;0#1!6?\u{1b}\\";
+    let mut sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    assert_eq!(sixel_image.pixel_size(), (6, 6));
+
+    sixel_image.draw_rect_outline(Rect::new(0, 0, 6, 6), 1);
+    sixel_image.draw_line((1, 1), (4, 4), SixelColor::Rgb(0, 100, 0));
+    assert_eq!(
+        sixel_image.color_registers.get(&0),
+        Some(&SixelColor::Rgb(0, 100, 0))
+    );
+    sixel_image.fill_rect(Rect::new(4, 1, 10, 1), SixelColor::Rgb(100, 0, 0));
+    assert_eq!(sixel_image.color_registers.len(), 2); // red already had register 1
+    sixel_image.flood_fill(1, 4, SixelColor::Rgb(0, 0, 100));
+    sixel_image.flood_fill(3, 3, 1);
+    assert_eq!(
+        format!("{:?}", sixel_image.pixels),
+        "[[1, 1, 1, 1, 1, 1], \
+          [1, 0, x, x, 1, 1], \
+          [1, 2, 0, x, x, 1], \
+          [1, 2, 2, 1, x, 1], \
+          [1, 2, 2, 2, 0, 1], \
+          [1, 1, 1, 1, 1, 1]]"
+    );
+}
+
+#[test]
+fn draw_partially_outside_of_images() {
+    let sample = "\u{1b}P0;1q\"1;1;6;6#1;2;100;0;0#1!6?\u{1b}\\";
+    let mut sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    sixel_image.draw_rect_outline(Rect::new(3, 3, usize::MAX, usize::MAX), 1);
+    sixel_image.draw_line((0, 0), (usize::MAX, usize::MAX), 1);
+    sixel_image.draw_line((usize::MAX, 1), (0, 1), 1);
+    sixel_image.draw_line((2, 7), (2, 5), 1);
+    sixel_image.draw_rect_outline(Rect::new(6, 0, 2, 2), 1);
+    assert_eq!(
+        format!("{:?}", sixel_image.pixels),
+        "[[1, x, x, x, x, x], \
+          [1, 1, 1, 1, 1, 1], \
+          [x, x, 1, x, x, x], \
+          [x, x, x, 1, 1, 1], \
+          [x, x, x, 1, 1, x], \
+          [x, x, 1, 1, x, 1]]"
+    );
+}
+
+#[test]
+fn allocate_color_registers_by_color() {
+    let sample = "\u{1b}P0;1q#0;2;0;0;0#2;2;100;0;0#2~\u{1b}\\";
+    let mut sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    assert_eq!(sixel_image.register_limit(), 256);
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(100, 0, 0)), 2);
+    // HLS colors match the RGB color they resolve to
+    assert_eq!(sixel_image.register_for(SixelColor::Hsl(120, 50, 100)), 2);
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(0, 100, 0)), 1);
+    assert_eq!(
+        sixel_image.color_registers.get(&1),
+        Some(&SixelColor::Rgb(0, 100, 0))
+    );
+
+    sixel_image.set_register_limit(3);
+    // every register is taken, so the closest color is used
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(0, 0, 100)), 0);
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(90, 10, 10)), 2);
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(10, 10, 10)), 0);
+    assert_eq!(sixel_image.color_registers.len(), 3);
+}
+
+#[test]
+fn allocate_color_registers_around_used_undefined_registers() {
+    // the raster attributes pad the background with register 0, which is never defined
+    let padded = "\u{1b}Pq\"1;1;4;6#1;2;100;0;0#1~~\u{1b}\\";
+    let mut sixel_image = SixelImage::new(padded.as_bytes()).unwrap();
+    sixel_image.fill_rect(Rect::new(0, 0, 1, 1), SixelColor::Rgb(0, 0, 100));
+    assert_eq!(sixel_image.rgb_at(0, 0), Some((0, 0, 255)));
+    assert_eq!(sixel_image.rgb_at(3, 0), Some((0, 0, 0)));
+    // the default color of a used register is matched like a defined one
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(0, 0, 0)), 0);
+
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;90;90;80#1~$#2?~$#3??~\u{1b}\\";
+    let mut sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(0, 0, 100)), 0);
+    assert_eq!(sixel_image.register_for(SixelColor::Rgb(0, 100, 100)), 4);
+    assert_eq!(sixel_image.rgb_at(2, 0), Some((51, 204, 51)));
+}
+
+#[test]
+fn remap_colors_and_replace_palette() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;90;90;80#1~$#2?~$#3??~\u{1b}\\";
+    let mut inverted = SixelImage::new(sample.as_bytes()).unwrap();
+    inverted.remap_colors(|color| {
+        let (r, g, b) = color.to_rgb8();
+        let invert = |component: u8| ((255 - component) as u16 * 100 / 255) as u8;
+        SixelColor::Rgb(invert(r), invert(g), invert(b))
+    });
+    assert_eq!(inverted.rgb_at(0, 0), Some((0, 255, 255)));
+    assert_eq!(inverted.rgb_at(1, 0), Some((23, 23, 51)));
+    // register 3 was never defined, so its default VT340 color (dark green) is inverted
+    assert_eq!(inverted.rgb_at(2, 0), Some((204, 51, 204)));
+
+    let mut black_and_white = SixelImage::new(sample.as_bytes()).unwrap();
+    let palette = [SixelColor::Rgb(0, 0, 0), SixelColor::Rgb(100, 100, 100)];
+    black_and_white.replace_palette(&palette).unwrap();
+    assert_eq!(black_and_white.color_registers.len(), 2);
+    assert_eq!(black_and_white.rgb_at(0, 0), Some((0, 0, 0)));
+    assert_eq!(black_and_white.rgb_at(1, 0), Some((255, 255, 255)));
+    assert_eq!(black_and_white.rgb_at(2, 0), Some((0, 0, 0)));
+    assert!(black_and_white.replace_palette(&[]).is_err());
+}
+
+#[test]
+fn convert_to_grayscale_and_monochrome() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#3;2;100;100;100#4;2;0;0;0#5;2;0;0;100\
+                  #1~$#2?~$#3??~$#4???~$#5????~\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+
+    let grayscale = sixel_image.to_grayscale();
+    // red, blue, white and black, the second blue register is merged into the first one
+    assert_eq!(
+        grayscale.color_registers.values().collect::<Vec<_>>(),
+        vec![
+            &SixelColor::Rgb(30, 30, 30),
+            &SixelColor::Rgb(11, 11, 11),
+            &SixelColor::Rgb(100, 100, 100),
+            &SixelColor::Rgb(0, 0, 0),
+        ]
+    );
+    assert_eq!(grayscale.rgb_at(4, 0), grayscale.rgb_at(1, 0));
+
+    let monochrome = sixel_image.to_monochrome(128);
+    assert_eq!(monochrome.color_registers.len(), 1);
+    assert_eq!(
+        monochrome.serialize_monochrome(),
+        "\u{1b}P0;1;0q??~??\u{1b}\\"
+    );
+    assert_eq!(
+        sixel_image.serialize_monochrome(),
+        "\u{1b}P0;1;0q!5~\u{1b}\\"
+    );
+}
+
+#[test]
+fn import_rgba_with_alpha_options() {
+    // opaque red, half transparent red, almost fully transparent red and fully transparent
+    let rgba = [255, 0, 0, 255, 255, 0, 0, 128, 255, 0, 0, 10, 255, 0, 0, 0];
+
+    let thresholded = SixelImage::from_rgba(4, 1, &rgba, &Default::default()).unwrap();
+    assert_eq!(
+        thresholded.to_rgba(),
+        vec![255, 0, 0, 255, 255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+
+    let options = RgbaImportOptions::new()
+        .alpha_threshold(1)
+        .blend_background((255, 255, 255));
+    let blended = SixelImage::from_rgba(4, 1, &rgba, &options).unwrap();
+    assert_eq!(blended.rgb_at(0, 0), Some((255, 0, 0)));
+    assert_eq!(blended.rgb_at(1, 0), Some((255, 128, 128)));
+    assert_eq!(blended.rgb_at(2, 0), Some((255, 245, 245)));
+    assert_eq!(blended.rgb_at(3, 0), None);
+
+    let opaque_options = RgbaImportOptions::new()
+        .alpha_threshold(0)
+        .blend_background((0, 0, 0));
+    let opaque = SixelImage::from_rgba(4, 1, &rgba, &opaque_options).unwrap();
+    assert_eq!(opaque.rgb_at(3, 0), Some((0, 0, 0)));
+    assert!(!opaque.dcs().transparent_bg());
+
+    // importers of formats with alpha take the same options
+    let mut pam = b"P7\nWIDTH 4\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nENDHDR\n".to_vec();
+    pam.extend_from_slice(&rgba);
+    let blended_pam = SixelImage::from_pam(&pam, &options).unwrap();
+    assert_eq!(blended_pam.to_rgba(), blended.to_rgba());
+    let kitty = format!(
+        "\u{1b}_Gf=32,s=4,v=1;{}\u{1b}\\",
+        crate::base64::encode(&rgba)
+    );
+    let blended_kitty = SixelImage::from_kitty(kitty.as_bytes(), &options).unwrap();
+    assert_eq!(blended_kitty.to_rgba(), blended.to_rgba());
+}
+
+#[test]
+fn compare_images_by_resolved_colors() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1~~$#2??~~\u{1b}\\";
+    let renumbered = "\u{1b}P0;1q#7;2;0;0;100#3;1;120;50;100#3~~$#7??~~\u{1b}\\";
+    let changed = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1~~$#2??~}\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let renumbered_image = SixelImage::new(renumbered.as_bytes()).unwrap();
+    let changed_image = SixelImage::new(changed.as_bytes()).unwrap();
+    assert_eq!(sixel_image, renumbered_image);
+    assert_ne!(sixel_image, changed_image);
+
+    let difference = sixel_image.difference(&renumbered_image).unwrap();
+    assert_eq!(difference.differing_pixels, 0);
+    assert_eq!(difference.max_difference, 0.0);
+    let difference = sixel_image.difference(&changed_image).unwrap();
+    assert_eq!(difference.differing_pixels, 1);
+    assert_eq!(difference.max_difference, 1.0); // the pixel is off in one of the images
+    assert!((difference.mean_difference - 1.0 / 24.0).abs() < 1e-9);
+    let mut recolored_image = sixel_image.clone();
+    recolored_image
+        .color_registers
+        .insert(1, SixelColor::Rgb(90, 0, 0));
+    let difference = sixel_image.difference(&recolored_image).unwrap();
+    assert_eq!(difference.differing_pixels, 12);
+    assert!(difference.max_difference > 0.0 && difference.max_difference < 0.1);
+    assert!(sixel_image
+        .difference(&SixelImage::from_rgba(1, 1, &[0; 4], &Default::default()).unwrap())
+        .is_err());
+
+    let diff_image = sixel_image.diff_image(&changed_image).unwrap();
+    assert_eq!(diff_image.rgb_at(3, 0), Some((255, 0, 0)));
+    assert_eq!(diff_image.rgb_at(3, 5), Some((199, 199, 199)));
+    assert_eq!(diff_image.rgb_at(0, 0), Some((209, 209, 209)));
+}
+
+#[test]
+fn content_hash_ignores_register_numbers() {
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1~~$#2??~~\u{1b}\\";
+    let renumbered = "\u{1b}Pq#7;2;0;0;100#3;1;120;50;100#3~~$#7??~~\u{1b}\\";
+    let changed = "\u{1b}P0;1q#1;2;100;0;0#2;2;0;0;100#1~~$#2??~}\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let renumbered_image = SixelImage::new(renumbered.as_bytes()).unwrap();
+    let changed_image = SixelImage::new(changed.as_bytes()).unwrap();
+    assert_eq!(sixel_image.content_hash(), renumbered_image.content_hash());
+    assert_ne!(sixel_image.content_hash(), changed_image.content_hash());
+
+    let mut deduplicated = std::collections::HashSet::new();
+    deduplicated.insert(sixel_image);
+    deduplicated.insert(renumbered_image);
+    deduplicated.insert(changed_image);
+    assert_eq!(deduplicated.len(), 2);
+
+    // the hash is stable, so it can be persisted
+    let red_column = SixelImage::new("\u{1b}Pq#0;2;100;0;0~\u{1b}\\".as_bytes()).unwrap();
+    assert_eq!(red_column.content_hash(), 0x0d7b_b5c6_4c14_5fe2);
+}
+
+#[test]
+fn canonical_serialization_ignores_encoding_choices() {
+    // the same picture: registers numbered and defined in a different order, a duplicate register
+    // for the same color, HLS rather than RGB colors, an undefined register relying on the
+    // defaults and different repeats
+    let sample = "\u{1b}P0;1q#5;2;0;0;100#9;2;100;0;0#9~~~$#5???!3~-#3@@\u{1b}\\";
+    let reencoded =
+        "\u{1b}P0;1q#1;1;120;50;100#2;1;0;50;100#4;2;0;0;100#1!3~$#2???~#4~~-#6;2;20;80;20#6@@\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let reencoded_image = SixelImage::new(reencoded.as_bytes()).unwrap();
+    let canonical = sixel_image.serialize_canonical();
+    assert!(sixel_image == reencoded_image);
+    assert_eq!(canonical, reencoded_image.serialize_canonical());
+    assert_eq!(
+        canonical,
+        "\u{1b}P0;1;0q#0;2;100;0;0#1;2;0;0;100#2;2;20;80;20#0!3~!3?$#1!3?!3~-#2@@\u{1b}\\"
+    );
+    assert!(SixelImage::new(canonical.as_bytes()).unwrap() == sixel_image);
+
+    // rows padded with pixels that are off, beyond the width of the image
+    let ragged = "\u{1b}P0;1q#1;2;100;0;0#1~-#1~~\u{1b}\\";
+    let padded = "\u{1b}P0;1q#1;2;100;0;0#1~-#1~~???\u{1b}\\";
+    let ragged_image = SixelImage::new(ragged.as_bytes()).unwrap();
+    let padded_image = SixelImage::new(padded.as_bytes()).unwrap();
+    assert!(ragged_image == padded_image);
+    assert_eq!(
+        ragged_image.serialize_canonical(),
+        padded_image.serialize_canonical()
+    );
+    assert!(
+        SixelImage::new(padded_image.serialize_canonical().as_bytes()).unwrap() == padded_image
+    );
+}
+
+#[test]
+fn compare_images_with_ragged_rows() {
+    // the second band is wider than the first row, which sets the width of the image
+    let sample = "\u{1b}P0;1q#1;2;100;0;0#1~-#1~~\u{1b}\\";
+    let changed = "\u{1b}P0;1q#1;2;100;0;0#1~-#1~?\u{1b}\\";
+    let padded = "\u{1b}P0;1q#1;2;100;0;0#1~-#1~~???\u{1b}\\";
+    let sixel_image = SixelImage::new(sample.as_bytes()).unwrap();
+    let changed_image = SixelImage::new(changed.as_bytes()).unwrap();
+    let padded_image = SixelImage::new(padded.as_bytes()).unwrap();
+    assert_eq!(sixel_image.pixel_size(), changed_image.pixel_size());
+    assert!(sixel_image != changed_image);
+    assert!(sixel_image == padded_image);
+    let difference = sixel_image.difference(&changed_image).unwrap();
+    assert_eq!(difference.differing_pixels, 6);
+    let diff_image = sixel_image.diff_image(&changed_image).unwrap();
+    assert_eq!(diff_image.rgb_at(1, 6), Some((255, 0, 0)));
+    assert_ne!(sixel_image.content_hash(), changed_image.content_hash());
+    assert_eq!(sixel_image.content_hash(), padded_image.content_hash());
+    assert_eq!(
+        changed_image.diff(&sixel_image).unwrap(),
+        vec![Rect::new(1, 6, 1, 6)]
+    );
+}
@@ -0,0 +1,189 @@
+use crate::{luminance, SixelImage};
+
+/// How [`SixelImage::render_text`] represents pixels with text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextRenderMode {
+    /// Two pixels per cell using half-block characters (▀▄), colored with 24-bit SGR escapes
+    HalfBlocksTrueColor,
+    /// Two pixels per cell using half-block characters (▀▄), colored with 256-color SGR escapes
+    HalfBlocks256,
+    /// Eight monochrome pixels per cell using braille characters, a dot is drawn for every pixel
+    /// that is on (eg. images from monochrome sixel devices)
+    Braille,
+    /// Like [`TextRenderMode::Braille`], but a dot is only drawn for pixels that are on and at
+    /// least as bright as the given luminance (eg. 128 for light text on a dark background)
+    BrailleThreshold(u8),
+}
+
+type Rgb = (u8, u8, u8);
+
+pub(crate) struct TextRenderer<'a> {
+    sixel_image: &'a SixelImage,
+    columns: usize,
+    rows: usize,
+}
+
+impl<'a> TextRenderer<'a> {
+    pub fn new(sixel_image: &'a SixelImage, columns: usize, rows: usize) -> Self {
+        TextRenderer {
+            sixel_image,
+            columns,
+            rows,
+        }
+    }
+    pub fn render(&self, mode: TextRenderMode) -> Vec<String> {
+        match mode {
+            TextRenderMode::HalfBlocksTrueColor => self.render_half_blocks(true),
+            TextRenderMode::HalfBlocks256 => self.render_half_blocks(false),
+            TextRenderMode::Braille => self.render_braille(0),
+            TextRenderMode::BrailleThreshold(threshold) => self.render_braille(threshold),
+        }
+    }
+    // the color of the image pixel under the virtual pixel at x/y out of a grid of
+    // grid_width x grid_height virtual pixels covering the whole image
+    fn sample(&self, x: usize, y: usize, grid_width: usize, grid_height: usize) -> Option<Rgb> {
+        let (height, width) = self.sixel_image.pixel_size();
+        self.sixel_image
+            .rgb_at(x * width / grid_width, y * height / grid_height)
+    }
+    fn render_half_blocks(&self, true_color: bool) -> Vec<String> {
+        let grid_width = self.columns;
+        let grid_height = self.rows * 2;
+        let mut lines = vec![];
+        for row in 0..self.rows {
+            let mut line = String::new();
+            let mut current_style = SgrStyle::default();
+            for column in 0..self.columns {
+                let top = self.sample(column, row * 2, grid_width, grid_height);
+                let bottom = self.sample(column, row * 2 + 1, grid_width, grid_height);
+                let (character, style) = match (top, bottom) {
+                    (None, None) => (' ', SgrStyle::default()),
+                    (Some(top), None) => ('▀', SgrStyle::new(Some(top), None)),
+                    (None, Some(bottom)) => ('▄', SgrStyle::new(Some(bottom), None)),
+                    (Some(top), Some(bottom)) if top == bottom => {
+                        ('█', SgrStyle::new(Some(top), None))
+                    }
+                    (Some(top), Some(bottom)) => ('▀', SgrStyle::new(Some(top), Some(bottom))),
+                };
+                current_style.transition_to(style, true_color, &mut line);
+                line.push(character);
+            }
+            current_style.transition_to(SgrStyle::default(), true_color, &mut line);
+            lines.push(line);
+        }
+        lines
+    }
+    fn render_braille(&self, threshold: u8) -> Vec<String> {
+        // the bit of each dot in a braille character, indexed by [y][x] inside the cell
+        const DOT_BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+        let grid_width = self.columns * 2;
+        let grid_height = self.rows * 4;
+        let mut lines = vec![];
+        for row in 0..self.rows {
+            let mut line = String::new();
+            for column in 0..self.columns {
+                let mut dots = 0;
+                for (y, row_bits) in DOT_BITS.iter().enumerate() {
+                    for (x, dot_bit) in row_bits.iter().enumerate() {
+                        let is_lit = self
+                            .sample(column * 2 + x, row * 4 + y, grid_width, grid_height)
+                            .map(|rgb| luminance(rgb) >= threshold)
+                            .unwrap_or(false);
+                        if is_lit {
+                            dots |= dot_bit;
+                        }
+                    }
+                }
+                let character = if dots == 0 {
+                    ' '
+                } else {
+                    char::from_u32(0x2800 + dots).unwrap_or(' ')
+                };
+                line.push(character);
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SgrStyle {
+    foreground: Option<Rgb>,
+    background: Option<Rgb>,
+}
+
+impl SgrStyle {
+    fn new(foreground: Option<Rgb>, background: Option<Rgb>) -> Self {
+        SgrStyle {
+            foreground,
+            background,
+        }
+    }
+    // appends the escapes needed to change from this style to `style`, and becomes it
+    fn transition_to(&mut self, style: SgrStyle, true_color: bool, append_to: &mut String) {
+        if *self == style {
+            return;
+        }
+        let clears_foreground = self.foreground.is_some() && style.foreground.is_none();
+        let clears_background = self.background.is_some() && style.background.is_none();
+        if clears_foreground || clears_background {
+            append_to.push_str("\u{1b}[0m");
+            *self = SgrStyle::default();
+        }
+        if let Some(foreground) = style
+            .foreground
+            .filter(|_| self.foreground != style.foreground)
+        {
+            append_to.push_str(&format!("\u{1b}[38;{}m", sgr_color(foreground, true_color)));
+        }
+        if let Some(background) = style
+            .background
+            .filter(|_| self.background != style.background)
+        {
+            append_to.push_str(&format!("\u{1b}[48;{}m", sgr_color(background, true_color)));
+        }
+        *self = style;
+    }
+}
+
+fn sgr_color((r, g, b): Rgb, true_color: bool) -> String {
+    if true_color {
+        format!("2;{};{};{}", r, g, b)
+    } else {
+        format!("5;{}", ansi_256_color((r, g, b)))
+    }
+}
+
+// the closest color out of the 6x6x6 color cube and the grayscale ramp of the 256-color palette
+fn ansi_256_color((r, g, b): Rgb) -> u8 {
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let closest_level = |component: u8| {
+        (0..6)
+            .min_by_key(|index| (CUBE_LEVELS[*index] as i32 - component as i32).abs())
+            .unwrap_or(0)
+    };
+    let (r_index, g_index, b_index) = (closest_level(r), closest_level(g), closest_level(b));
+    let cube_color = (
+        CUBE_LEVELS[r_index],
+        CUBE_LEVELS[g_index],
+        CUBE_LEVELS[b_index],
+    );
+    let average = (r as usize + g as usize + b as usize) / 3;
+    let gray_index = std::cmp::min(average.saturating_sub(3) / 10, 23);
+    let gray_level = (8 + gray_index * 10) as u8;
+    let gray_color = (gray_level, gray_level, gray_level);
+    let distance = |(r2, g2, b2): Rgb| {
+        let (dr, dg, db) = (
+            r as i32 - r2 as i32,
+            g as i32 - g2 as i32,
+            b as i32 - b2 as i32,
+        );
+        dr * dr + dg * dg + db * db
+    };
+    if distance(gray_color) < distance(cube_color) {
+        (232 + gray_index) as u8
+    } else {
+        (16 + 36 * r_index + 6 * g_index + b_index) as u8
+    }
+}